@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::models::chain::{ChainMetadata, Explorer, NativeCurrency};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChainResponse {
+    chain_id: u64,
+    name: String,
+    short_name: Option<String>,
+    native_currency: Option<NativeCurrency>,
+    #[serde(default)]
+    explorers: Vec<Explorer>,
+}
+
+// fetches chain data in the `chains.json` format used by chainlist
+pub struct ChainRegistryClient {
+    url: String,
+    client: Client,
+}
+
+impl ChainRegistryClient {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: Client::new(),
+        }
+    }
+
+    pub async fn fetch_chains(&self) -> Result<Vec<ChainMetadata>> {
+        let chains = self
+            .client
+            .get(&self.url)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await
+            .context("failed to request chains")?
+            .error_for_status()
+            .context("chains responded with error")?
+            .json::<Vec<Value>>()
+            .await
+            .context("failed to parse chains")?;
+
+        // a single malformed chain should not drop the whole registry
+        Ok(chains
+            .into_iter()
+            .filter_map(|chain| serde_json::from_value::<ChainResponse>(chain).ok())
+            .map(|chain| ChainMetadata {
+                name: Some(chain.name),
+                short_name: chain.short_name,
+                native_currency: chain.native_currency,
+                explorers: chain.explorers,
+                ..ChainMetadata::new(&chain.chain_id.to_string())
+            })
+            .collect())
+    }
+}
@@ -1,70 +1,173 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
 
-use anyhow::{anyhow, Context, Result};
-use regex_macro::regex;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, StatusCode,
+};
+use rocket::tokio::{fs, sync::Mutex};
 
-const RESOURCE_URL: &'static str =
-    "https://raw.githubusercontent.com/DefiLlama/chainlist/main/constants/extraRpcs.js";
-const TARGET_START_LINE: &'static str = "export const extraRpcs = {";
-const TARGET_STOP_LINE: &'static str = "const allExtraRpcs = mergeDeep(llamaNodesRpcs, extraRpcs);";
+use crate::{
+    client::{
+        chainlist_parser::parse_extra_rpcs,
+        source::{ChainToUpstreams, RpcSource},
+    },
+    models::upstream::{Tracking, Upstream},
+};
 
-#[derive(Clone, Copy)]
-pub struct ChainlistClient;
+const SOURCE_NAME: &str = "chainlist";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct CachedResource {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    // modification time of file:// mirrors
+    modified_at: Option<SystemTime>,
+    upstreams: ChainToUpstreams,
+}
+
+// mirrors are tried in order, unchanged resources are not downloaded and parsed again
+pub struct ChainlistClient {
+    urls: Vec<String>,
+    client: Client,
+    url_to_cached: Mutex<HashMap<String, CachedResource>>,
+}
 
 impl ChainlistClient {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(urls: Vec<String>) -> Self {
+        Self {
+            urls,
+            client: Client::new(),
+            url_to_cached: Mutex::new(HashMap::new()),
+        }
     }
 
-    pub async fn fetch_rpcs(&self) -> Result<HashMap<String, Vec<String>>> {
-        let content = reqwest::get(RESOURCE_URL)
-            .await
-            .context("failed to make resposne to github")?
-            .text()
-            .await
-            .context("failed to parse response")?;
-
-        let lines: Vec<&str> = content.split('\n').collect();
+    async fn fetch_http(&self, url: &str) -> Result<ChainToUpstreams> {
+        let mut request = self.client.get(url).timeout(FETCH_TIMEOUT);
+        if let Some(cached) = self.url_to_cached.lock().await.get(url) {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
 
-        let start_line = lines
-            .iter()
-            .position(|&value| value == TARGET_START_LINE)
-            .ok_or(anyhow!("failed to locate start line"))?;
-        let stop_line = lines
-            .iter()
-            .position(|&value| value == TARGET_STOP_LINE)
-            .ok_or(anyhow!("failed to locate stop line"))?;
+        let response = request.send().await.context("failed to make request")?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = self.url_to_cached.lock().await.get(url) {
+                log::debug!("chainlist at {url} is not modified");
+                return Ok(cached.upstreams.clone());
+            }
+            bail!("not modified response without cached resource");
+        }
 
-        let target_object = &lines[start_line..stop_line];
-        let mut chain_to_rpc: HashMap<String, Vec<String>> = HashMap::new();
+        let response = response
+            .error_for_status()
+            .context("responded with error")?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|val: &HeaderValue| val.to_str().ok())
+                .map(str::to_owned)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let content = response.text().await.context("failed to read response")?;
 
-        let chain_id_re = regex!(r"^(\d+): \{$");
-        let url_re = regex!(
-            r#""(https:\/\/(www\.)?[-a-zA-Z0-9@:%._\+~#=]{1,256}\.[a-zA-Z0-9()]{1,6}\b([-a-zA-Z0-9()@:%_\+.~#?&//=]*))""#
+        let upstreams = parse_upstreams(&content)?;
+        self.url_to_cached.lock().await.insert(
+            url.to_owned(),
+            CachedResource {
+                etag,
+                last_modified,
+                modified_at: None,
+                upstreams: upstreams.clone(),
+            },
         );
 
-        let mut chain_id = "";
-        for &line in target_object {
-            if let Some(caps) = chain_id_re.captures(line.trim()) {
-                chain_id = &caps.get(1).map(|m| m.as_str()).expect("unreachable");
-                continue;
+        Ok(upstreams)
+    }
+
+    async fn fetch_file(&self, url: &str, path: &str) -> Result<ChainToUpstreams> {
+        let modified_at = fs::metadata(path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .context(format!("failed to read metadata of {path}"))?;
+        if let Some(cached) = self.url_to_cached.lock().await.get(url) {
+            if cached.modified_at == Some(modified_at) {
+                log::debug!("chainlist at {url} is not modified");
+                return Ok(cached.upstreams.clone());
             }
+        }
 
-            let Some(caps) = url_re.captures(line.trim()) else {
-                continue;
-            };
+        let content = fs::read_to_string(path)
+            .await
+            .context(format!("failed to read {path}"))?;
+
+        let upstreams = parse_upstreams(&content)?;
+        self.url_to_cached.lock().await.insert(
+            url.to_owned(),
+            CachedResource {
+                etag: None,
+                last_modified: None,
+                modified_at: Some(modified_at),
+                upstreams: upstreams.clone(),
+            },
+        );
 
-            let url: &str = &caps.get(1).map(|m| m.as_str()).expect("unreachable");
+        Ok(upstreams)
+    }
+}
+
+#[async_trait]
+impl RpcSource for ChainlistClient {
+    fn name(&self) -> &str {
+        SOURCE_NAME
+    }
 
-            if String::from(url).contains("polysplit") {
-                continue;
+    async fn fetch_rpcs(&self) -> Result<ChainToUpstreams> {
+        for url in &self.urls {
+            let upstreams = match url.strip_prefix("file://") {
+                Some(path) => self.fetch_file(url, path).await,
+                None => self.fetch_http(url).await,
+            };
+
+            match upstreams {
+                Ok(upstreams) => return Ok(upstreams),
+                Err(err) => log::warn!("failed to fetch chainlist from {url}: {err:#}"),
             }
-            chain_to_rpc
-                .entry(chain_id.to_owned())
-                .or_insert_with(Vec::new)
-                .push(url.to_owned());
         }
 
-        Ok(chain_to_rpc)
+        bail!("all chainlist mirrors failed")
+    }
+}
+
+fn parse_upstreams(content: &str) -> Result<ChainToUpstreams> {
+    let chain_to_rpcs = parse_extra_rpcs(content).context("failed to parse extraRpcs")?;
+
+    let mut chain_to_upstreams: ChainToUpstreams = HashMap::new();
+    for (chain_id, rpcs) in chain_to_rpcs {
+        let upstreams = rpcs
+            .iter()
+            .filter(|rpc| rpc.url.starts_with("https://") || rpc.url.starts_with("wss://"))
+            .map(|rpc| {
+                let tracking = rpc.tracking.as_deref().and_then(|tracking| {
+                    tracking
+                        .parse::<Tracking>()
+                        .map_err(|err| log::debug!("{} has {err}", rpc.url))
+                        .ok()
+                });
+                Upstream::new(&rpc.url, SOURCE_NAME).with_tracking(tracking)
+            })
+            .collect();
+        chain_to_upstreams.insert(chain_id, upstreams);
     }
+
+    Ok(chain_to_upstreams)
 }
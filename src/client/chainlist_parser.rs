@@ -0,0 +1,1029 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+const TARGET_DECLARATION: &str = "extraRpcs";
+const MAX_RESOLVE_DEPTH: usize = 32;
+const STATEMENT_KEYWORDS: [&str; 8] = [
+    "const", "let", "var", "export", "import", "function", "class", "async",
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ChainlistParseError {
+    #[error("syntax error at {line}:{column}: {message}")]
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("`{0}` declaration not found")]
+    MissingDeclaration(String),
+    #[error("invalid value at {path}: {message}")]
+    InvalidValue { path: String, message: String },
+    #[error("no rpcs found in `{0}`")]
+    Empty(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainlistRpc {
+    pub url: String,
+    pub tracking: Option<String>,
+    pub tracking_details: Option<String>,
+}
+
+// parses `extraRpcs` object from chainlist `constants/extraRpcs.js` into rpcs by chain id
+pub fn parse_extra_rpcs(
+    content: &str,
+) -> Result<HashMap<String, Vec<ChainlistRpc>>, ChainlistParseError> {
+    let bindings = Parser::new(content)?.parse_module()?;
+    let resolver = Resolver {
+        bindings: &bindings,
+    };
+
+    let extra_rpcs =
+        bindings
+            .get(TARGET_DECLARATION)
+            .ok_or(ChainlistParseError::MissingDeclaration(
+                TARGET_DECLARATION.to_owned(),
+            ))?;
+    let chains = match resolver.resolve(extra_rpcs, 0) {
+        Some(JsValue::Object(chains)) => chains,
+        _ => {
+            return Err(ChainlistParseError::InvalidValue {
+                path: TARGET_DECLARATION.to_owned(),
+                message: String::from("expected object"),
+            })
+        }
+    };
+
+    let mut chain_to_rpcs: HashMap<String, Vec<ChainlistRpc>> = HashMap::new();
+    for (chain_id, chain) in resolver.entries(&chains, 0) {
+        let path = format!("{TARGET_DECLARATION}.{chain_id}");
+        if chain_id.is_empty() || !chain_id.chars().all(|c| c.is_ascii_digit()) {
+            log::debug!("skipping {path}: not a chain id");
+            continue;
+        }
+
+        let rpcs = match resolver.field(&chain, "rpcs") {
+            Some(JsValue::Array(rpcs)) => rpcs,
+            _ => {
+                log::warn!("skipping {path}: expected `rpcs` array");
+                continue;
+            }
+        };
+
+        for (i, rpc) in resolver.items(&rpcs, 0).iter().enumerate() {
+            match resolver.to_rpc(rpc) {
+                Ok(rpc) => chain_to_rpcs.entry(chain_id.clone()).or_default().push(rpc),
+                Err(message) => log::warn!("skipping {path}.rpcs[{i}]: {message}"),
+            }
+        }
+    }
+
+    if chain_to_rpcs.is_empty() {
+        return Err(ChainlistParseError::Empty(TARGET_DECLARATION.to_owned()));
+    }
+
+    Ok(chain_to_rpcs)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<JsValue>),
+    Object(Vec<ObjectEntry>),
+    Spread(Box<JsValue>),
+    Ref(Vec<String>),
+    Unsupported,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ObjectEntry {
+    Property(String, JsValue),
+    Spread(JsValue),
+}
+
+struct Resolver<'a> {
+    bindings: &'a HashMap<String, JsValue>,
+}
+
+impl Resolver<'_> {
+    fn resolve(&self, value: &JsValue, depth: usize) -> Option<JsValue> {
+        if depth > MAX_RESOLVE_DEPTH {
+            return None;
+        }
+
+        match value {
+            JsValue::Ref(path) => {
+                let (root, fields) = path.split_first()?;
+                let mut value = self.resolve(self.bindings.get(root)?, depth + 1)?;
+                for field in fields {
+                    value = match value {
+                        JsValue::Object(entries) => self
+                            .entries(&entries, depth + 1)
+                            .into_iter()
+                            .rev()
+                            .find(|(key, _)| key == field)
+                            .map(|(_, value)| value)?,
+                        JsValue::Array(items) => {
+                            let index = field.parse::<usize>().ok()?;
+                            self.items(&items, depth + 1).get(index)?.clone()
+                        }
+                        _ => return None,
+                    };
+                    value = self.resolve(&value, depth + 1)?;
+                }
+                Some(value)
+            }
+            JsValue::Unsupported | JsValue::Spread(_) => None,
+            value => Some(value.clone()),
+        }
+    }
+
+    fn entries(&self, entries: &[ObjectEntry], depth: usize) -> Vec<(String, JsValue)> {
+        let mut resolved = Vec::new();
+        for entry in entries {
+            match entry {
+                ObjectEntry::Property(key, value) => resolved.push((key.clone(), value.clone())),
+                ObjectEntry::Spread(value) => match self.resolve(value, depth + 1) {
+                    Some(JsValue::Object(spread)) => {
+                        resolved.extend(self.entries(&spread, depth + 1))
+                    }
+                    _ => log::debug!("skipping unresolved object spread: {value:?}"),
+                },
+            }
+        }
+        resolved
+    }
+
+    fn items(&self, items: &[JsValue], depth: usize) -> Vec<JsValue> {
+        let mut resolved = Vec::new();
+        for item in items {
+            match item {
+                JsValue::Spread(value) => match self.resolve(value, depth + 1) {
+                    Some(JsValue::Array(spread)) => resolved.extend(self.items(&spread, depth + 1)),
+                    _ => log::debug!("skipping unresolved array spread: {value:?}"),
+                },
+                item => resolved.push(item.clone()),
+            }
+        }
+        resolved
+    }
+
+    fn field(&self, value: &JsValue, name: &str) -> Option<JsValue> {
+        let JsValue::Object(entries) = self.resolve(value, 0)? else {
+            return None;
+        };
+        let (_, value) = self
+            .entries(&entries, 0)
+            .into_iter()
+            .rev()
+            .find(|(key, _)| key == name)?;
+        self.resolve(&value, 0)
+    }
+
+    fn string_field(&self, value: &JsValue, name: &str) -> Result<Option<String>, String> {
+        match self.field(value, name) {
+            None | Some(JsValue::Null) => Ok(None),
+            Some(JsValue::String(value)) => Ok(Some(value)),
+            Some(other) => Err(format!("expected string `{name}`, got {other:?}")),
+        }
+    }
+
+    fn to_rpc(&self, value: &JsValue) -> Result<ChainlistRpc, String> {
+        match self.resolve(value, 0) {
+            Some(JsValue::String(url)) => Ok(ChainlistRpc {
+                url,
+                tracking: None,
+                tracking_details: None,
+            }),
+            Some(rpc @ JsValue::Object(_)) => Ok(ChainlistRpc {
+                url: self
+                    .string_field(&rpc, "url")?
+                    .ok_or(String::from("missing `url`"))?,
+                tracking: self.string_field(&rpc, "tracking")?,
+                // details are free-form, so references which can't be resolved are ignored
+                tracking_details: self.string_field(&rpc, "trackingDetails").ok().flatten(),
+            }),
+            Some(other) => Err(format!("expected string or object, got {other:?}")),
+            None => Err(format!("unresolved value {value:?}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Punct(char),
+    Spread,
+    Str(String),
+    // template literal with `${}` substitutions
+    Template,
+    Regex,
+    Number(String),
+    Ident(String),
+    Other(char),
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+    newline_before: bool,
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(content: &'a str) -> Self {
+        Self {
+            chars: content.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> ChainlistParseError {
+        ChainlistParseError::Syntax {
+            line: self.line,
+            column: self.column,
+            message: message.to_owned(),
+        }
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>, ChainlistParseError> {
+        let mut tokens = Vec::new();
+        let mut newline_before = false;
+
+        loop {
+            let Some(&c) = self.chars.peek() else {
+                tokens.push(Token {
+                    kind: TokenKind::Eof,
+                    line: self.line,
+                    column: self.column,
+                    newline_before,
+                });
+                return Ok(tokens);
+            };
+
+            if c.is_whitespace() {
+                newline_before |= c == '\n';
+                self.bump();
+                continue;
+            }
+
+            let (line, column) = (self.line, self.column);
+            self.bump();
+
+            let kind = match c {
+                '/' if self.chars.peek() == Some(&'/') => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.bump();
+                    }
+                    continue;
+                }
+                '/' if self.chars.peek() == Some(&'*') => {
+                    self.bump();
+                    let mut prev = ' ';
+                    loop {
+                        let Some(c) = self.bump() else {
+                            return Err(self.error("unterminated comment"));
+                        };
+                        newline_before |= c == '\n';
+                        if prev == '*' && c == '/' {
+                            break;
+                        }
+                        prev = c;
+                    }
+                    continue;
+                }
+                '/' if regex_allowed(tokens.last()) => self.regex()?,
+                '"' | '\'' => TokenKind::Str(self.string(c)?),
+                '`' => self.template()?,
+                '.' if self.chars.peek() == Some(&'.') => {
+                    self.bump();
+                    if self.bump() != Some('.') {
+                        return Err(self.error("expected `...`"));
+                    }
+                    TokenKind::Spread
+                }
+                '{' | '}' | '[' | ']' | '(' | ')' | ',' | ':' | ';' | '.' | '=' => {
+                    TokenKind::Punct(c)
+                }
+                c if c.is_ascii_digit() => {
+                    let mut number = String::from(c);
+                    while let Some(&c) = self.chars.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                            break;
+                        }
+                        number.push(c);
+                        self.bump();
+                    }
+                    TokenKind::Number(number)
+                }
+                c if c.is_alphabetic() || c == '_' || c == '$' => {
+                    let mut ident = String::from(c);
+                    while let Some(&c) = self.chars.peek() {
+                        if !(c.is_alphanumeric() || c == '_' || c == '$') {
+                            break;
+                        }
+                        ident.push(c);
+                        self.bump();
+                    }
+                    TokenKind::Ident(ident)
+                }
+                c => TokenKind::Other(c),
+            };
+
+            tokens.push(Token {
+                kind,
+                line,
+                column,
+                newline_before,
+            });
+            newline_before = false;
+        }
+    }
+
+    fn string(&mut self, quote: char) -> Result<String, ChainlistParseError> {
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => return Ok(value),
+                Some('\\') => value.push(self.escape()?),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, ChainlistParseError> {
+        let c = self
+            .bump()
+            .ok_or_else(|| self.error("unterminated escape"))?;
+        let digits = match c {
+            'n' => return Ok('\n'),
+            't' => return Ok('\t'),
+            'r' => return Ok('\r'),
+            'x' => 2,
+            'u' => 4,
+            c => return Ok(c),
+        };
+
+        let mut code = String::new();
+        for _ in 0..digits {
+            code.push(
+                self.bump()
+                    .ok_or_else(|| self.error("unterminated escape"))?,
+            );
+        }
+        u32::from_str_radix(&code, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error("invalid escape"))
+    }
+
+    // the body is skipped, quotes and slashes inside of it do not start tokens
+    fn regex(&mut self) -> Result<TokenKind, ChainlistParseError> {
+        let mut class = false;
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(self.error("unterminated regex")),
+                Some('\\') => {
+                    if self.bump().is_none() {
+                        return Err(self.error("unterminated regex"));
+                    }
+                }
+                Some('[') => class = true,
+                Some(']') => class = false,
+                Some('/') if !class => break,
+                Some(_) => {}
+            }
+        }
+        while self.chars.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.bump();
+        }
+
+        Ok(TokenKind::Regex)
+    }
+
+    fn template(&mut self) -> Result<TokenKind, ChainlistParseError> {
+        let mut value = String::new();
+        let mut substitutions = false;
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated template literal")),
+                Some('`') => break,
+                Some('\\') => value.push(self.escape()?),
+                Some('$') if self.chars.peek() == Some(&'{') => {
+                    substitutions = true;
+                    let mut depth = 0;
+                    while let Some(c) = self.bump() {
+                        match c {
+                            '{' => depth += 1,
+                            '}' if depth == 1 => break,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                }
+                Some(c) => value.push(c),
+            }
+        }
+
+        match substitutions {
+            true => Ok(TokenKind::Template),
+            false => Ok(TokenKind::Str(value)),
+        }
+    }
+}
+
+// a slash after a value is a division, otherwise it starts a regex literal
+fn regex_allowed(previous: Option<&Token>) -> bool {
+    let Some(previous) = previous else {
+        return true;
+    };
+    match &previous.kind {
+        TokenKind::Ident(ident) => [
+            "return",
+            "typeof",
+            "instanceof",
+            "in",
+            "of",
+            "new",
+            "delete",
+            "void",
+            "throw",
+            "case",
+            "do",
+            "else",
+            "yield",
+            "await",
+        ]
+        .contains(&ident.as_str()),
+        TokenKind::Number(_) | TokenKind::Str(_) | TokenKind::Template | TokenKind::Regex => false,
+        TokenKind::Punct(')' | ']' | '}') => false,
+        _ => true,
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(content: &str) -> Result<Self, ChainlistParseError> {
+        Ok(Self {
+            tokens: Lexer::new(content).tokenize()?,
+            position: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position.min(self.tokens.len() - 1)]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek().clone();
+        if token.kind != TokenKind::Eof {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        self.peek().kind == TokenKind::Punct(c)
+    }
+
+    fn is_ident(&self, name: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Ident(ident) if ident == name)
+    }
+
+    fn error(&self, token: &Token, message: String) -> ChainlistParseError {
+        ChainlistParseError::Syntax {
+            line: token.line,
+            column: token.column,
+            message,
+        }
+    }
+
+    fn expect_punct(&mut self, c: char, context: &str) -> Result<(), ChainlistParseError> {
+        let token = self.next();
+        if token.kind != TokenKind::Punct(c) {
+            return Err(self.error(
+                &token,
+                format!("expected `{c}` {context}, found {:?}", token.kind),
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_module(&mut self) -> Result<HashMap<String, JsValue>, ChainlistParseError> {
+        let mut bindings = HashMap::new();
+        loop {
+            let token = self.peek().clone();
+            match &token.kind {
+                TokenKind::Eof => return Ok(bindings),
+                TokenKind::Punct(';') => {
+                    self.next();
+                }
+                TokenKind::Ident(ident) if ident == "export" => {
+                    self.next();
+                    if self.is_ident("default") {
+                        self.next();
+                        self.parse_expression()?;
+                    }
+                }
+                TokenKind::Ident(ident) if ["const", "let", "var"].contains(&ident.as_str()) => {
+                    self.next();
+                    self.parse_declarations(&mut bindings)?;
+                }
+                TokenKind::Ident(ident)
+                    if ["function", "class", "async"].contains(&ident.as_str()) =>
+                {
+                    while !self.is_punct('{') && self.peek().kind != TokenKind::Eof {
+                        self.next();
+                    }
+                    self.skip_balanced()?;
+                }
+                _ => self.skip_statement()?,
+            }
+        }
+    }
+
+    fn parse_declarations(
+        &mut self,
+        bindings: &mut HashMap<String, JsValue>,
+    ) -> Result<(), ChainlistParseError> {
+        loop {
+            let token = self.next();
+            let TokenKind::Ident(name) = token.kind else {
+                // destructuring declarations are not supported
+                return self.skip_statement();
+            };
+
+            if self.is_punct('=') {
+                self.next();
+                let value = self.parse_expression()?;
+                bindings.insert(name, value);
+            }
+
+            if !self.is_punct(',') {
+                return Ok(());
+            }
+            self.next();
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<JsValue, ChainlistParseError> {
+        let token = self.peek().clone();
+        let value = match &token.kind {
+            TokenKind::Punct('{') => self.parse_object()?,
+            TokenKind::Punct('[') => self.parse_array()?,
+            TokenKind::Str(value) => {
+                self.next();
+                JsValue::String(value.clone())
+            }
+            TokenKind::Number(value) => {
+                self.next();
+                JsValue::Number(value.clone())
+            }
+            TokenKind::Ident(ident) => {
+                self.next();
+                match ident.as_str() {
+                    "true" => JsValue::Bool(true),
+                    "false" => JsValue::Bool(false),
+                    "null" | "undefined" => JsValue::Null,
+                    _ => self.parse_reference(ident.clone())?,
+                }
+            }
+            TokenKind::Other('-') => {
+                self.next();
+                let token = self.next();
+                match token.kind {
+                    TokenKind::Number(value) => JsValue::Number(format!("-{value}")),
+                    _ => JsValue::Unsupported,
+                }
+            }
+            TokenKind::Punct('}' | ']' | ')' | ',' | ';') | TokenKind::Eof => {
+                return Err(self.error(&token, format!("expected value, found {:?}", token.kind)))
+            }
+            _ => {
+                self.skip_expression()?;
+                return Ok(JsValue::Unsupported);
+            }
+        };
+
+        // binary operators, ternaries, arrow functions and so on
+        if matches!(
+            self.peek().kind,
+            TokenKind::Other(_) | TokenKind::Punct('=')
+        ) {
+            self.skip_expression()?;
+            return Ok(JsValue::Unsupported);
+        }
+
+        Ok(value)
+    }
+
+    fn parse_reference(&mut self, root: String) -> Result<JsValue, ChainlistParseError> {
+        let mut path = vec![root];
+        loop {
+            if self.is_punct('.') {
+                self.next();
+                let token = self.next();
+                let TokenKind::Ident(field) = token.kind else {
+                    return Err(
+                        self.error(&token, String::from("expected property name after `.`"))
+                    );
+                };
+                path.push(field);
+            } else if self.is_punct('[') {
+                self.next();
+                let field = match self.parse_expression()? {
+                    JsValue::String(field) | JsValue::Number(field) => Some(field),
+                    _ => None,
+                };
+                self.expect_punct(']', "after computed property")?;
+                match field {
+                    Some(field) => path.push(field),
+                    None => return Ok(JsValue::Unsupported),
+                }
+            } else if self.is_punct('(') {
+                self.skip_balanced()?;
+                return Ok(JsValue::Unsupported);
+            } else {
+                return Ok(JsValue::Ref(path));
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsValue, ChainlistParseError> {
+        let open = self.next();
+        let mut entries = Vec::new();
+        loop {
+            let token = self.next();
+            let key = match token.kind {
+                TokenKind::Punct('}') => return Ok(JsValue::Object(entries)),
+                TokenKind::Spread => {
+                    entries.push(ObjectEntry::Spread(self.parse_expression()?));
+                    self.object_separator(&open)?;
+                    continue;
+                }
+                TokenKind::Ident(key) | TokenKind::Str(key) | TokenKind::Number(key) => key,
+                TokenKind::Punct('[') => {
+                    // computed keys can't be evaluated, entry is kept unreachable
+                    self.parse_expression()?;
+                    self.expect_punct(']', "after computed key")?;
+                    String::new()
+                }
+                TokenKind::Eof => {
+                    return Err(self.error(&open, String::from("object is never closed")))
+                }
+                ref kind => {
+                    return Err(self.error(&token, format!("expected object key, found {kind:?}")))
+                }
+            };
+
+            if self.is_punct(':') {
+                self.next();
+                let value = self.parse_expression()?;
+                entries.push(ObjectEntry::Property(key, value));
+            } else if self.is_punct('(') {
+                // method definition
+                self.skip_balanced()?;
+                self.skip_balanced()?;
+                entries.push(ObjectEntry::Property(key, JsValue::Unsupported));
+            } else {
+                // shorthand property
+                entries.push(ObjectEntry::Property(key.clone(), JsValue::Ref(vec![key])));
+            }
+
+            self.object_separator(&open)?;
+        }
+    }
+
+    fn object_separator(&mut self, open: &Token) -> Result<(), ChainlistParseError> {
+        if self.is_punct('}') {
+            return Ok(());
+        }
+        let token = self.next();
+        if token.kind != TokenKind::Punct(',') {
+            return Err(self.error(
+                &token,
+                format!(
+                    "expected `,` or `}}` in object opened at {}:{}, found {:?}",
+                    open.line, open.column, token.kind
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_array(&mut self) -> Result<JsValue, ChainlistParseError> {
+        let open = self.next();
+        let mut items = Vec::new();
+        loop {
+            if self.is_punct(']') {
+                self.next();
+                return Ok(JsValue::Array(items));
+            }
+            if self.peek().kind == TokenKind::Eof {
+                return Err(self.error(&open, String::from("array is never closed")));
+            }
+
+            if self.peek().kind == TokenKind::Spread {
+                self.next();
+                items.push(JsValue::Spread(Box::new(self.parse_expression()?)));
+            } else {
+                items.push(self.parse_expression()?);
+            }
+
+            if self.is_punct(']') {
+                continue;
+            }
+            let token = self.next();
+            if token.kind != TokenKind::Punct(',') {
+                return Err(self.error(
+                    &token,
+                    format!(
+                        "expected `,` or `]` in array opened at {}:{}, found {:?}",
+                        open.line, open.column, token.kind
+                    ),
+                ));
+            }
+        }
+    }
+
+    fn at_expression_end(&self) -> bool {
+        let token = self.peek();
+        match &token.kind {
+            TokenKind::Punct(',' | ';' | '}' | ']' | ')' | ':') | TokenKind::Eof => true,
+            TokenKind::Ident(ident) => {
+                token.newline_before && STATEMENT_KEYWORDS.contains(&ident.as_str())
+            }
+            _ => false,
+        }
+    }
+
+    fn skip_expression(&mut self) -> Result<(), ChainlistParseError> {
+        while !self.at_expression_end() || self.is_punct(':') {
+            match self.peek().kind {
+                TokenKind::Punct('{' | '[' | '(') => self.skip_balanced()?,
+                _ => {
+                    self.next();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_statement(&mut self) -> Result<(), ChainlistParseError> {
+        // the first token is always consumed, even if it starts a statement
+        match self.peek().kind {
+            TokenKind::Punct('{' | '[' | '(') => self.skip_balanced()?,
+            TokenKind::Punct(';') | TokenKind::Eof => {
+                self.next();
+                return Ok(());
+            }
+            _ => {
+                self.next();
+            }
+        }
+
+        loop {
+            let token = self.peek();
+            match &token.kind {
+                TokenKind::Eof => return Ok(()),
+                TokenKind::Punct(';') => {
+                    self.next();
+                    return Ok(());
+                }
+                TokenKind::Punct('{' | '[' | '(') => self.skip_balanced()?,
+                TokenKind::Ident(ident)
+                    if token.newline_before && STATEMENT_KEYWORDS.contains(&ident.as_str()) =>
+                {
+                    return Ok(())
+                }
+                _ => {
+                    self.next();
+                }
+            }
+        }
+    }
+
+    fn skip_balanced(&mut self) -> Result<(), ChainlistParseError> {
+        let open = self.next();
+        let close = match open.kind {
+            TokenKind::Punct('{') => '}',
+            TokenKind::Punct('[') => ']',
+            TokenKind::Punct('(') => ')',
+            ref kind => return Err(self.error(&open, format!("expected bracket, found {kind:?}"))),
+        };
+
+        loop {
+            match self.peek().kind {
+                TokenKind::Eof => {
+                    return Err(self.error(&open, format!("`{close}` is never found")))
+                }
+                TokenKind::Punct(c) if c == close => {
+                    self.next();
+                    return Ok(());
+                }
+                TokenKind::Punct('{' | '[' | '(') => self.skip_balanced()?,
+                _ => {
+                    self.next();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // hand-written in the layout of DefiLlama/chainlist `constants/extraRpcs.js`,
+    // not saved copies of the upstream file
+    const EXTRA_RPCS: &str = include_str!("../../tests/fixtures/chainlist/extraRpcs.js");
+    const LEGACY_EXTRA_RPCS: &str =
+        include_str!("../../tests/fixtures/chainlist/extraRpcs_legacy.js");
+
+    fn urls(rpcs: &[ChainlistRpc]) -> Vec<&str> {
+        rpcs.iter().map(|rpc| rpc.url.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_object_and_string_rpcs() {
+        let chain_to_rpcs = parse_extra_rpcs(EXTRA_RPCS).unwrap();
+
+        assert_eq!(
+            urls(&chain_to_rpcs["1"]),
+            vec![
+                "https://eth.llamarpc.com",
+                "https://endpoints.omniatech.io/v1/eth/mainnet/public",
+                "https://rpc.ankr.com/eth",
+                "wss://ethereum-rpc.publicnode.com",
+                "https://eth.drpc.org",
+            ]
+        );
+        assert_eq!(
+            chain_to_rpcs["1"][0],
+            ChainlistRpc {
+                url: String::from("https://eth.llamarpc.com"),
+                tracking: Some(String::from("none")),
+                tracking_details: Some(String::from("No user data is collected by LlamaNodes")),
+            }
+        );
+        assert_eq!(chain_to_rpcs["1"][1].tracking, None);
+        assert_eq!(chain_to_rpcs["1"][2].tracking.as_deref(), Some("limited"));
+    }
+
+    #[test]
+    fn resolves_variable_references_and_spreads() {
+        let chain_to_rpcs = parse_extra_rpcs(EXTRA_RPCS).unwrap();
+
+        assert_eq!(
+            urls(&chain_to_rpcs["56"]),
+            vec![
+                "https://bsc-dataseed.bnbchain.org",
+                "https://bsc-dataseed1.defibit.io",
+                "https://binance.llamarpc.com",
+            ]
+        );
+        assert_eq!(
+            chain_to_rpcs["56"][2].tracking_details.as_deref(),
+            Some("No user data is collected by LlamaNodes")
+        );
+        assert_eq!(
+            urls(&chain_to_rpcs["137"]),
+            vec!["https://polygon-rpc.com", "https://polygon.drpc.org"]
+        );
+    }
+
+    #[test]
+    fn skips_entries_which_can_not_be_resolved() {
+        let chain_to_rpcs = parse_extra_rpcs(EXTRA_RPCS).unwrap();
+
+        // imported `llamaNodesRpcs` spread and template literal with substitutions
+        assert_eq!(
+            urls(&chain_to_rpcs["10"]),
+            vec!["https://mainnet.optimism.io"]
+        );
+        assert!(!chain_to_rpcs.contains_key("42161"));
+    }
+
+    #[test]
+    fn skips_keys_which_are_not_chain_ids() {
+        let chain_to_rpcs = parse_extra_rpcs(
+            r#"export const extraRpcs = { "": { rpcs: ["https://a.io"] }, eth: { rpcs: ["https://b.io"] }, 1: { rpcs: ["https://c.io"] } };"#,
+        )
+        .unwrap();
+
+        assert_eq!(chain_to_rpcs.len(), 1);
+        assert_eq!(urls(&chain_to_rpcs["1"]), vec!["https://c.io"]);
+    }
+
+    #[test]
+    fn skips_regex_literals() {
+        let content = r#"
+const isWs = /^wss?:\/\/[^"'/]+/i;
+const ratio = 10 / 2 / 1;
+export const extraRpcs = {
+  1: {
+    rpcs: [
+      "https://a.io",
+      { url: "https://b.io", tracking: "none", match: /["`]/g },
+    ],
+  },
+  10: { rpcs: [{ url: "https://c.io", check: (url) => !/'/.test(url) }] },
+};
+"#;
+        let chain_to_rpcs = parse_extra_rpcs(content).unwrap();
+
+        assert_eq!(
+            urls(&chain_to_rpcs["1"]),
+            vec!["https://a.io", "https://b.io"]
+        );
+        assert_eq!(urls(&chain_to_rpcs["10"]), vec!["https://c.io"]);
+    }
+
+    #[test]
+    fn parses_legacy_format() {
+        let chain_to_rpcs = parse_extra_rpcs(LEGACY_EXTRA_RPCS).unwrap();
+
+        assert_eq!(
+            urls(&chain_to_rpcs["1"]),
+            vec![
+                "https://api.mycryptoapi.com/eth",
+                "https://cloudflare-eth.com",
+                "https://rpc.polysplit.cloud/v1/chain/1",
+            ]
+        );
+        assert_eq!(
+            urls(&chain_to_rpcs["56"]),
+            vec!["https://bsc-dataseed.binance.org"]
+        );
+    }
+
+    #[test]
+    fn reports_syntax_error_location() {
+        let content = "export const extraRpcs = {\n  1: {\n    rpcs: [\"https://a.io\" \"https://b.io\"],\n  },\n};\n";
+
+        assert_eq!(
+            parse_extra_rpcs(content),
+            Err(ChainlistParseError::Syntax {
+                line: 3,
+                column: 27,
+                message: String::from(
+                    "expected `,` or `]` in array opened at 3:11, found Str(\"https://b.io\")"
+                ),
+            })
+        );
+    }
+
+    #[test]
+    fn reports_missing_declaration() {
+        assert_eq!(
+            parse_extra_rpcs("export const otherRpcs = {};"),
+            Err(ChainlistParseError::MissingDeclaration(String::from(
+                "extraRpcs"
+            )))
+        );
+    }
+
+    #[test]
+    fn reports_invalid_shape() {
+        assert_eq!(
+            parse_extra_rpcs("export const extraRpcs = [];"),
+            Err(ChainlistParseError::InvalidValue {
+                path: String::from("extraRpcs"),
+                message: String::from("expected object"),
+            })
+        );
+        assert_eq!(
+            parse_extra_rpcs("export const extraRpcs = { 1: { rpcs: [] } };"),
+            Err(ChainlistParseError::Empty(String::from("extraRpcs")))
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::{
+    client::source::{ChainToUpstreams, RpcSource},
+    models::upstream::{Upstream, UpstreamTier},
+};
+
+const SOURCE_NAME: &str = "env";
+const ENV_PREFIX: &str = "RPC_URLS_";
+
+// reads `RPC_URLS_<chain id>=<url>,<url>` variables, those are our own nodes
+#[derive(Debug, Clone, Copy)]
+pub struct EnvRpcSource;
+
+impl EnvRpcSource {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[async_trait]
+impl RpcSource for EnvRpcSource {
+    fn name(&self) -> &str {
+        SOURCE_NAME
+    }
+
+    async fn fetch_rpcs(&self) -> Result<ChainToUpstreams> {
+        let mut chain_to_upstreams: ChainToUpstreams = HashMap::new();
+        for (name, value) in std::env::vars() {
+            let Some(chain_id) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+
+            chain_to_upstreams.insert(
+                chain_id.to_owned(),
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(|url| Upstream::new(url, SOURCE_NAME).with_tier(UpstreamTier::Primary))
+                    .collect(),
+            );
+        }
+
+        Ok(chain_to_upstreams)
+    }
+}
@@ -0,0 +1,45 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rocket::tokio::fs;
+
+use crate::{
+    client::source::{to_upstreams, ChainToUpstreams, RpcSource, SourceRpc},
+    models::upstream::UpstreamTier,
+};
+
+const SOURCE_NAME: &str = "file";
+
+#[derive(Debug, Clone)]
+pub struct FileRpcSource {
+    path: PathBuf,
+}
+
+impl FileRpcSource {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl RpcSource for FileRpcSource {
+    fn name(&self) -> &str {
+        SOURCE_NAME
+    }
+
+    async fn fetch_rpcs(&self) -> Result<ChainToUpstreams> {
+        let content = fs::read_to_string(&self.path)
+            .await
+            .context(format!("failed to read {}", self.path.display()))?;
+
+        let rpcs: HashMap<String, Vec<SourceRpc>> =
+            match self.path.extension().and_then(|ext| ext.to_str()) {
+                Some("toml") => toml::from_str(&content).context("failed to parse toml")?,
+                _ => serde_json::from_str(&content).context("failed to parse json")?,
+            };
+
+        // rpcs listed explicitly are considered vetted
+        Ok(to_upstreams(SOURCE_NAME, UpstreamTier::Secondary, rpcs))
+    }
+}
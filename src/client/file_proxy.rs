@@ -0,0 +1,73 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rocket::tokio::{fs, sync::Mutex};
+
+use crate::{
+    client::proxy_provider::{FetchedProxies, ProxyProvider},
+    models::proxy::ProxyConfig,
+};
+
+const PROVIDER_NAME: &str = "file";
+
+// reads `user:pass@host:port` lines, empty lines and lines starting with `#` are skipped
+#[derive(Debug)]
+pub struct FileProxyProvider {
+    path: PathBuf,
+    loaded_modified_at: Mutex<Option<SystemTime>>,
+}
+
+impl FileProxyProvider {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            loaded_modified_at: Mutex::new(None),
+        }
+    }
+
+    async fn modified_at(&self) -> Result<SystemTime> {
+        fs::metadata(&self.path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .context(format!(
+                "failed to read metadata of {}",
+                self.path.display()
+            ))
+    }
+}
+
+#[async_trait]
+impl ProxyProvider for FileProxyProvider {
+    fn name(&self) -> &str {
+        PROVIDER_NAME
+    }
+
+    async fn fetch_proxies(&self) -> Result<FetchedProxies> {
+        let modified_at = self.modified_at().await?;
+        let content = fs::read_to_string(&self.path)
+            .await
+            .context(format!("failed to read {}", self.path.display()))?;
+
+        let proxies = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse::<ProxyConfig>()
+                    .context(format!("failed to parse proxy line {line}"))
+            })
+            .collect::<Result<Vec<ProxyConfig>>>()?;
+
+        *self.loaded_modified_at.lock().await = Some(modified_at);
+        Ok(proxies.into())
+    }
+
+    async fn has_changed(&self) -> bool {
+        let Ok(modified_at) = self.modified_at().await else {
+            return false;
+        };
+
+        *self.loaded_modified_at.lock().await != Some(modified_at)
+    }
+}
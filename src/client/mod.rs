@@ -1,2 +1,12 @@
+pub mod chain_registry;
 pub mod chainlist;
+pub mod chainlist_parser;
+pub mod env;
+pub mod file;
+pub mod file_proxy;
+pub mod proxy_checker;
+pub mod proxy_provider;
 pub mod proxyseller;
+pub mod registry;
+pub mod source;
+pub mod static_proxy;
@@ -0,0 +1,259 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::{
+    body,
+    client::conn,
+    header::{CONTENT_TYPE, HOST, PROXY_AUTHORIZATION},
+    Body, Request,
+};
+use reqwest::Url;
+use rocket::tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{lookup_host, TcpStream},
+    task, time,
+};
+use serde_json::{json, Value};
+use tokio_native_tls::{native_tls, TlsConnector, TlsStream};
+use tokio_socks::{tcp::Socks5Stream, IntoTargetAddr};
+
+use crate::models::proxy::{ProxyCheck, ProxyConfig, ProxyProtocol};
+use crate::util::unix_timestamp;
+
+const MAX_CONNECT_RESPONSE_LEN: usize = 8 * 1024;
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+// sends a json rpc request through the proxy the way reqwest does for rpc traffic:
+// https upstreams through a tunnel, plain http ones as absolute-form requests to http proxies.
+// the connection is built by hand to time every phase of it
+pub struct ProxyChecker {
+    url: Url,
+    host: String,
+    port: u16,
+    // with the port when it is not the default one of the scheme
+    host_header: String,
+    timeout: Duration,
+}
+
+impl ProxyChecker {
+    pub fn new(url: &str, timeout: Duration) -> Result<Self> {
+        let url = Url::parse(url).context("failed to parse proxy check url")?;
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!("unsupported proxy check url scheme {}", url.scheme());
+        }
+        let host = url
+            .host_str()
+            .ok_or(anyhow!("proxy check url has no host"))?
+            .to_owned();
+        let port = url
+            .port_or_known_default()
+            .ok_or(anyhow!("proxy check url has no port"))?;
+        let host_header = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.clone(),
+        };
+
+        Ok(Self {
+            url,
+            host,
+            port,
+            host_header,
+            timeout,
+        })
+    }
+
+    pub async fn check(&self, proxy_config: &ProxyConfig) -> Result<ProxyCheck> {
+        time::timeout(self.timeout, self.request(proxy_config))
+            .await
+            .map_err(|_| anyhow!("proxy check timed out"))?
+    }
+
+    async fn request(&self, proxy_config: &ProxyConfig) -> Result<ProxyCheck> {
+        let start = Instant::now();
+        let proxy_port = u16::try_from(proxy_config.port).context("invalid proxy port")?;
+        let socket = TcpStream::connect((proxy_config.host.as_str(), proxy_port))
+            .await
+            .context("failed to connect to proxy")?;
+        let connect_ms = start.elapsed().as_millis();
+
+        let https = self.url.scheme() == "https";
+        // plain http requests are sent to http proxies as they are, without a tunnel
+        let forwarded = !https
+            && matches!(
+                proxy_config.protocol,
+                ProxyProtocol::Http | ProxyProtocol::Https
+            );
+        let stream: Box<dyn Stream> = match proxy_config.protocol {
+            ProxyProtocol::Http if forwarded => Box::new(socket),
+            ProxyProtocol::Http => Box::new(self.connect_tunnel(socket, proxy_config).await?),
+            ProxyProtocol::Https => {
+                let stream = tls_connect(&proxy_config.host, socket)
+                    .await
+                    .context("failed tls handshake with proxy")?;
+                if forwarded {
+                    Box::new(stream)
+                } else {
+                    Box::new(self.connect_tunnel(stream, proxy_config).await?)
+                }
+            }
+            ProxyProtocol::Socks5 | ProxyProtocol::Socks5h => {
+                Box::new(self.connect_socks(socket, proxy_config).await?)
+            }
+        };
+
+        let tls_start = Instant::now();
+        let (stream, tls_ms): (Box<dyn Stream>, Option<u128>) = if https {
+            let stream = tls_connect(&self.host, stream)
+                .await
+                .context("failed tls handshake with upstream")?;
+            (Box::new(stream), Some(tls_start.elapsed().as_millis()))
+        } else {
+            (stream, None)
+        };
+
+        let (mut sender, connection) = conn::handshake(stream)
+            .await
+            .context("failed http handshake")?;
+        task::spawn(async move {
+            let _ = connection.await;
+        });
+
+        let rpc_call = json!({
+            "method": "eth_chainId",
+            "params": [],
+            "id": 1,
+            "jsonrpc": "2.0",
+        });
+        // absolute-form for forwarded requests, origin-form with the query otherwise
+        let mut target = self.url.path().to_owned();
+        if let Some(query) = self.url.query() {
+            target.push_str(&format!("?{query}"));
+        }
+        if forwarded {
+            target = format!("{}://{}{target}", self.url.scheme(), self.host_header);
+        }
+        let mut request = Request::post(target.as_str())
+            .header(HOST, &self.host_header)
+            .header(CONTENT_TYPE, "application/json");
+        if forwarded && !proxy_config.username.is_empty() {
+            request = request.header(PROXY_AUTHORIZATION, basic_auth(proxy_config));
+        }
+        let request = request
+            .body(Body::from(rpc_call.to_string()))
+            .context("failed to build request")?;
+        let response = sender
+            .send_request(request)
+            .await
+            .context("failed to send request")?;
+        if !response.status().is_success() {
+            bail!("upstream responded with {}", response.status());
+        }
+        let content = body::to_bytes(response.into_body())
+            .await
+            .context("failed to read response")?;
+        let response = serde_json::from_slice::<Value>(&content).context("invalid response")?;
+        if response.get("result").is_none() {
+            bail!("no result in response: {response}");
+        }
+
+        Ok(ProxyCheck {
+            connect_ms,
+            tls_ms,
+            total_ms: start.elapsed().as_millis(),
+            checked_at: unix_timestamp(),
+        })
+    }
+
+    async fn connect_tunnel<S: Stream>(
+        &self,
+        mut stream: S,
+        proxy_config: &ProxyConfig,
+    ) -> Result<S> {
+        let address = format!("{}:{}", self.host, self.port);
+        let mut request = format!("CONNECT {address} HTTP/1.1\r\nHost: {address}\r\n");
+        if !proxy_config.username.is_empty() {
+            request.push_str(&format!(
+                "Proxy-Authorization: {}\r\n",
+                basic_auth(proxy_config)
+            ));
+        }
+        request.push_str("\r\n");
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .context("failed to send connect request")?;
+
+        // the proxy sends nothing after the headers until the tunnel is used,
+        // so reading byte by byte does not consume tunneled data
+        let mut response: Vec<u8> = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() > MAX_CONNECT_RESPONSE_LEN {
+                bail!("connect response is too long");
+            }
+            let byte = stream
+                .read_u8()
+                .await
+                .context("failed to read connect response")?;
+            response.push(byte);
+        }
+
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        if status_line.split_whitespace().nth(1) != Some("200") {
+            bail!("proxy refused tunnel: {status_line}");
+        }
+
+        Ok(stream)
+    }
+
+    async fn connect_socks(
+        &self,
+        socket: TcpStream,
+        proxy_config: &ProxyConfig,
+    ) -> Result<Socks5Stream<TcpStream>> {
+        // socks5h leaves name resolution to the proxy
+        let target = match proxy_config.protocol {
+            ProxyProtocol::Socks5h => (self.host.as_str(), self.port).into_target_addr()?,
+            _ => lookup_host((self.host.as_str(), self.port))
+                .await
+                .context("failed to resolve upstream")?
+                .next()
+                .ok_or(anyhow!("upstream has no address"))?
+                .into_target_addr()?,
+        };
+
+        let stream = if proxy_config.username.is_empty() {
+            Socks5Stream::connect_with_socket(socket, target).await
+        } else {
+            Socks5Stream::connect_with_password_and_socket(
+                socket,
+                target,
+                &proxy_config.username,
+                &proxy_config.password,
+            )
+            .await
+        };
+
+        stream.context("failed socks handshake")
+    }
+}
+
+fn basic_auth(proxy_config: &ProxyConfig) -> String {
+    let credentials = STANDARD.encode(format!(
+        "{}:{}",
+        proxy_config.username, proxy_config.password
+    ));
+    format!("Basic {credentials}")
+}
+
+async fn tls_connect<S: Stream>(domain: &str, stream: S) -> Result<TlsStream<S>> {
+    let connector = native_tls::TlsConnector::new().context("failed to build tls connector")?;
+    TlsConnector::from(connector)
+        .connect(domain, stream)
+        .await
+        .context("failed tls handshake")
+}
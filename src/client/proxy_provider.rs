@@ -0,0 +1,37 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::models::proxy::ProxyConfig;
+
+#[derive(Debug, Default)]
+pub struct FetchedProxies {
+    pub proxies: Vec<ProxyConfig>,
+    // tags of the parts which failed to be fetched, their proxies stay in the pool
+    pub failed_tags: Vec<String>,
+}
+
+impl From<Vec<ProxyConfig>> for FetchedProxies {
+    fn from(proxies: Vec<ProxyConfig>) -> Self {
+        Self {
+            proxies,
+            failed_tags: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait ProxyProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn fetch_proxies(&self) -> Result<FetchedProxies>;
+
+    // providers without a check api trust their proxies
+    async fn check_proxy(&self, _proxy_config: &ProxyConfig) -> Result<bool> {
+        Ok(true)
+    }
+
+    // true when proxies should be fetched again before the next rotation
+    async fn has_changed(&self) -> bool {
+        false
+    }
+}
@@ -1,30 +1,62 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
 use serde::Deserialize;
 
-use crate::models::proxy::ProxyConfig;
+use crate::{
+    client::proxy_provider::{FetchedProxies, ProxyProvider},
+    models::proxy::{ProxyConfig, ProxyProtocol, ProxysellerOrder},
+};
 
-#[derive(Clone)]
-pub struct ProxysellerOrder(pub String, pub String);
+const PROVIDER_NAME: &str = "proxyseller";
 
-const PROXYSELLER_BASE_URL_API: &'static str = "https://proxy-seller.com/personal/api/v1";
+const PROXYSELLER_BASE_URL_API: &str = "https://proxy-seller.com/personal/api/v1";
 
 #[derive(Deserialize)]
 struct ProxysellerFetchProxiesDataElement {
     ip: String,
     port_http: i32,
+    port_socks: Option<i32>,
     login: String,
     password: String,
+    country: Option<String>,
     // id: String,
     // order_id: String,
     // protocol: String,
-    // port_socks: i32,
     // auth_ip: String,
-    // country: String,
     // status: String,
 }
 
+impl ProxysellerFetchProxiesDataElement {
+    fn to_proxy_config(
+        &self,
+        protocol: ProxyProtocol,
+        order: &ProxysellerOrder,
+    ) -> Option<ProxyConfig> {
+        let port = match protocol {
+            ProxyProtocol::Http | ProxyProtocol::Https => self.port_http,
+            ProxyProtocol::Socks5 | ProxyProtocol::Socks5h => self.port_socks?,
+        };
+
+        Some(ProxyConfig {
+            protocol,
+            host: self.ip.clone(),
+            port,
+            username: self.login.clone(),
+            password: self.password.clone(),
+            tag: Some(order.to_string()),
+            country: self
+                .country
+                .as_deref()
+                .filter(|country| !country.is_empty())
+                .map(str::to_uppercase),
+            asn: None,
+            daily_bytes: None,
+        })
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum ProxysellerFetchProxiesData {
@@ -53,68 +85,88 @@ struct ProxysellerResponse<D> {
 pub struct ProxysellerClient {
     api_key: String,
     orders: Vec<ProxysellerOrder>,
-    timeout_ms: i32,
+    protocol: ProxyProtocol,
+    request_timeout: Duration,
+    // proxies responding slower on the check are treated as bad
+    check_timeout: Duration,
 }
 
 impl ProxysellerClient {
     pub fn new(
         proxyseller_api_key: String,
         orders: Vec<ProxysellerOrder>,
-        timeout_ms: i32,
+        protocol: ProxyProtocol,
+        request_timeout: Duration,
+        check_timeout: Duration,
     ) -> Self {
         Self {
             api_key: proxyseller_api_key,
             orders,
-            timeout_ms,
+            protocol,
+            request_timeout,
+            check_timeout,
         }
     }
 
-    pub async fn fetch_proxies(&self) -> Result<Vec<ProxyConfig>> {
+    async fn fetch_order(&self, order: &ProxysellerOrder) -> Result<Vec<ProxyConfig>> {
+        let params = [("latest", "y"), ("orderId", &order.order_id)];
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{PROXYSELLER_BASE_URL_API}/{api_key}/proxy/list/{order_type}",
+                api_key = self.api_key,
+                order_type = order.order_type
+            ))
+            .query(&params)
+            .timeout(self.request_timeout)
+            .send()
+            .await
+            .context("failed to request proxy list")?
+            .json::<ProxysellerResponse<ProxysellerFetchProxiesDataWrapper>>()
+            .await
+            .context("failed to deserialize fetch request")?;
+
+        let elements: Vec<ProxysellerFetchProxiesDataElement> = match response.data.items {
+            ProxysellerFetchProxiesData::Array(arr) => arr,
+            ProxysellerFetchProxiesData::Record(map) => map.into_values().collect(),
+        };
         let mut proxy_configs: Vec<ProxyConfig> = Vec::new();
+        for el in elements {
+            match el.to_proxy_config(self.protocol, order) {
+                Some(proxy_config) => proxy_configs.push(proxy_config),
+                None => log::warn!("proxy {} has no {} port", el.ip, self.protocol),
+            }
+        }
+
+        Ok(proxy_configs)
+    }
+}
+
+#[async_trait]
+impl ProxyProvider for ProxysellerClient {
+    fn name(&self) -> &str {
+        PROVIDER_NAME
+    }
+
+    // orders are fetched independently, proxies of failed ones are kept by their tag
+    async fn fetch_proxies(&self) -> Result<FetchedProxies> {
+        let mut fetched = FetchedProxies::default();
         for order in &self.orders {
-            let params = [("latest", "y"), ("orderId", &order.1)];
-            let response = reqwest::Client::new()
-                .get(format!(
-                    "{PROXYSELLER_BASE_URL_API}/{api_key}/proxy/list/{order_type}",
-                    api_key = self.api_key,
-                    order_type = order.0
-                ))
-                .query(&params)
-                .send()
-                .await
-                .context("failed to request proxy list")?
-                .json::<ProxysellerResponse<ProxysellerFetchProxiesDataWrapper>>()
-                .await
-                .context("failed to deserialize fetch request")?;
-
-            match response.data.items {
-                ProxysellerFetchProxiesData::Array(arr) => {
-                    for el in arr {
-                        proxy_configs.push(ProxyConfig {
-                            host: el.ip,
-                            port: el.port_http,
-                            username: el.login,
-                            password: el.password,
-                        })
-                    }
-                }
-                ProxysellerFetchProxiesData::Record(map) => {
-                    for (_, el) in map {
-                        proxy_configs.push(ProxyConfig {
-                            host: el.ip,
-                            port: el.port_http,
-                            username: el.login,
-                            password: el.password,
-                        })
-                    }
+            match self.fetch_order(order).await {
+                Ok(proxy_configs) => fetched.proxies.extend(proxy_configs),
+                Err(err) => {
+                    log::error!("failed to fetch proxies of order {order}: {err:#}");
+                    fetched.failed_tags.push(order.to_string());
                 }
             }
         }
+        if fetched.failed_tags.len() == self.orders.len() {
+            bail!("all proxyseller orders failed");
+        }
 
-        Ok(proxy_configs)
+        Ok(fetched)
     }
 
-    pub async fn check_proxy(&self, proxy_config: &ProxyConfig) -> Result<bool> {
+    async fn check_proxy(&self, proxy_config: &ProxyConfig) -> Result<bool> {
         let proxy_string = format!(
             "{username}:{password}@{host}:{port}",
             username = proxy_config.username,
@@ -129,6 +181,7 @@ impl ProxysellerClient {
                 api_key = self.api_key
             ))
             .query(&params)
+            .timeout(self.request_timeout)
             .send()
             .await
             .context("failed to request check for proxy")?
@@ -136,10 +189,11 @@ impl ProxysellerClient {
             .await
             .context("failed to deserialize check request")?;
 
-        if response.status != "success".to_string() {
+        if response.status != "success" {
             bail!("check response status not equal success");
         }
 
-        Ok(response.data.valid && response.data.time < self.timeout_ms)
+        Ok(response.data.valid
+            && i64::from(response.data.time) < self.check_timeout.as_millis() as i64)
     }
 }
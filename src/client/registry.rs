@@ -0,0 +1,54 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+
+use crate::{
+    client::source::{to_upstreams, ChainToUpstreams, RpcSource, SourceRpc},
+    models::upstream::UpstreamTier,
+};
+
+const SOURCE_NAME: &str = "registry";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// fetches `{ "<chain id>": ["<url>", { "url": "<url>", "tracking": "none", "tier": "primary" }] }`
+// json from the given url
+#[derive(Debug, Clone)]
+pub struct RegistryClient {
+    client: Client,
+    url: String,
+}
+
+impl RegistryClient {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: Client::new(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl RpcSource for RegistryClient {
+    fn name(&self) -> &str {
+        SOURCE_NAME
+    }
+
+    async fn fetch_rpcs(&self) -> Result<ChainToUpstreams> {
+        let rpcs = self
+            .client
+            .get(&self.url)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await
+            .context("failed to request registry")?
+            .error_for_status()
+            .context("registry responded with error")?
+            .json::<HashMap<String, Vec<SourceRpc>>>()
+            .await
+            .context("failed to parse registry response")?;
+
+        Ok(to_upstreams(SOURCE_NAME, UpstreamTier::Secondary, rpcs))
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::models::{
+    budget::Budget,
+    egress::{Egress, GeoPolicy},
+    upstream::{Tracking, Upstream, UpstreamTier},
+};
+
+pub type ChainToUpstreams = HashMap<String, Vec<Upstream>>;
+
+#[async_trait]
+pub trait RpcSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn fetch_rpcs(&self) -> Result<ChainToUpstreams>;
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum SourceRpc {
+    Url(String),
+    Object {
+        url: String,
+        tracking: Option<Tracking>,
+        tier: Option<UpstreamTier>,
+        budget: Option<Budget>,
+        egress: Option<Egress>,
+        geo: Option<GeoPolicy>,
+    },
+}
+
+impl SourceRpc {
+    pub fn to_upstream(&self, source: &str, default_tier: UpstreamTier) -> Upstream {
+        match self {
+            SourceRpc::Url(url) => Upstream::new(url, source).with_tier(default_tier),
+            SourceRpc::Object {
+                url,
+                tracking,
+                tier,
+                budget,
+                egress,
+                geo,
+            } => Upstream::new(url, source)
+                .with_tracking(*tracking)
+                .with_tier(tier.unwrap_or(default_tier))
+                .with_budget(budget.clone())
+                .with_egress(*egress)
+                .with_geo(geo.clone()),
+        }
+    }
+}
+
+pub fn to_upstreams(
+    source: &str,
+    default_tier: UpstreamTier,
+    rpcs: HashMap<String, Vec<SourceRpc>>,
+) -> ChainToUpstreams {
+    rpcs.into_iter()
+        .map(|(chain_id, rpcs)| {
+            let upstreams = rpcs
+                .iter()
+                .map(|rpc| rpc.to_upstream(source, default_tier))
+                .collect();
+            (chain_id, upstreams)
+        })
+        .collect()
+}
+
+// sources are ordered by precedence, the same url from a later source is dropped.
+// `source_upstreams` keeps the latest successful fetch of every source and replaces failed ones
+pub async fn fetch_from_sources(
+    sources: &[Box<dyn RpcSource>],
+    source_upstreams: &mut HashMap<String, ChainToUpstreams>,
+) -> Result<ChainToUpstreams> {
+    let mut chain_to_upstreams: ChainToUpstreams = HashMap::new();
+    let mut failed_sources: Vec<&str> = Vec::new();
+
+    for source in sources {
+        match source.fetch_rpcs().await {
+            Ok(upstreams) => {
+                source_upstreams.insert(source.name().to_owned(), upstreams);
+            }
+            Err(err) => {
+                log::error!("failed to fetch rpcs from {}: {err:#}", source.name());
+                if !source_upstreams.contains_key(source.name()) {
+                    failed_sources.push(source.name());
+                    continue;
+                }
+                log::warn!("using last known rpcs of {}", source.name());
+            }
+        };
+        let Some(upstreams) = source_upstreams.get(source.name()) else {
+            continue;
+        };
+
+        for (chain_id, upstreams) in upstreams {
+            let chain_upstreams = chain_to_upstreams.entry(chain_id.clone()).or_default();
+            for upstream in upstreams {
+                if chain_upstreams.iter().all(|val| val.url != upstream.url) {
+                    chain_upstreams.push(upstream.clone());
+                }
+            }
+        }
+    }
+
+    if !sources.is_empty() && failed_sources.len() == sources.len() {
+        bail!("all rpc sources failed: {}", failed_sources.join(", "));
+    }
+
+    Ok(chain_to_upstreams)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockSource {
+        name: &'static str,
+        upstreams: Option<ChainToUpstreams>,
+    }
+
+    impl MockSource {
+        fn new(name: &'static str, chain_to_urls: &[(&str, &[&str])]) -> Self {
+            let upstreams = chain_to_urls
+                .iter()
+                .map(|(chain_id, urls)| {
+                    let upstreams = urls.iter().map(|url| Upstream::new(url, name)).collect();
+                    (chain_id.to_string(), upstreams)
+                })
+                .collect();
+            Self {
+                name,
+                upstreams: Some(upstreams),
+            }
+        }
+
+        fn failing(name: &'static str) -> Self {
+            Self {
+                name,
+                upstreams: None,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl RpcSource for MockSource {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn fetch_rpcs(&self) -> Result<ChainToUpstreams> {
+            match &self.upstreams {
+                Some(upstreams) => Ok(upstreams.clone()),
+                None => bail!("unreachable"),
+            }
+        }
+    }
+
+    fn sources(chain_upstreams: &[Upstream]) -> Vec<(&str, &str)> {
+        chain_upstreams
+            .iter()
+            .map(|upstream| (upstream.url.as_str(), upstream.source.as_str()))
+            .collect()
+    }
+
+    #[rocket::async_test]
+    async fn keeps_urls_of_the_preceding_source() {
+        let rpc_sources: Vec<Box<dyn RpcSource>> = vec![
+            Box::new(MockSource::new("env", &[("1", &["https://a.io"])])),
+            Box::new(MockSource::new(
+                "chainlist",
+                &[
+                    ("1", &["https://b.io", "https://a.io/"]),
+                    ("10", &["https://a.io"]),
+                ],
+            )),
+        ];
+        let mut source_upstreams = HashMap::new();
+
+        let chain_to_upstreams = fetch_from_sources(&rpc_sources, &mut source_upstreams)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            sources(&chain_to_upstreams["1"]),
+            vec![("https://a.io", "env"), ("https://b.io", "chainlist")]
+        );
+        // urls are deduplicated per chain only
+        assert_eq!(
+            sources(&chain_to_upstreams["10"]),
+            vec![("https://a.io", "chainlist")]
+        );
+        assert_eq!(source_upstreams.len(), 2);
+    }
+
+    #[rocket::async_test]
+    async fn falls_back_to_last_known_upstreams() {
+        let mut source_upstreams = HashMap::from([(
+            String::from("chainlist"),
+            HashMap::from([(
+                String::from("1"),
+                vec![Upstream::new("https://old.io", "chainlist")],
+            )]),
+        )]);
+        let rpc_sources: Vec<Box<dyn RpcSource>> = vec![
+            Box::new(MockSource::new("env", &[("1", &["https://a.io"])])),
+            Box::new(MockSource::failing("chainlist")),
+            Box::new(MockSource::failing("registry")),
+        ];
+
+        let chain_to_upstreams = fetch_from_sources(&rpc_sources, &mut source_upstreams)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            sources(&chain_to_upstreams["1"]),
+            vec![("https://a.io", "env"), ("https://old.io", "chainlist")]
+        );
+        assert!(!source_upstreams.contains_key("registry"));
+    }
+
+    #[rocket::async_test]
+    async fn fails_when_every_source_fails() {
+        let rpc_sources: Vec<Box<dyn RpcSource>> = vec![
+            Box::new(MockSource::failing("chainlist")),
+            Box::new(MockSource::failing("registry")),
+        ];
+
+        assert!(fetch_from_sources(&rpc_sources, &mut HashMap::new())
+            .await
+            .is_err());
+    }
+}
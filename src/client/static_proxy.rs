@@ -0,0 +1,31 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::{
+    client::proxy_provider::{FetchedProxies, ProxyProvider},
+    models::proxy::ProxyConfig,
+};
+
+const PROVIDER_NAME: &str = "static";
+
+#[derive(Debug, Clone)]
+pub struct StaticProxyProvider {
+    proxies: Vec<ProxyConfig>,
+}
+
+impl StaticProxyProvider {
+    pub fn new(proxies: Vec<ProxyConfig>) -> Self {
+        Self { proxies }
+    }
+}
+
+#[async_trait]
+impl ProxyProvider for StaticProxyProvider {
+    fn name(&self) -> &str {
+        PROVIDER_NAME
+    }
+
+    async fn fetch_proxies(&self) -> Result<FetchedProxies> {
+        Ok(self.proxies.clone().into())
+    }
+}
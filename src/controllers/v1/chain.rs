@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
-use rocket::{get, http::Status, post, serde::json::Json, tokio::sync::RwLock, State};
+use rocket::{get, http::Status, post, serde::json::Json, State};
 use rocket_governor::RocketGovernor;
 use rocket_okapi::openapi;
 use schemars::JsonSchema;
@@ -9,32 +9,47 @@ use serde_json::Value;
 
 use crate::{
     middleware::RateLimitGuard,
+    models::{
+        egress::Egress,
+        history::HistoryPoint,
+        rule::BannedUpstream,
+        traffic::{CountryTraffic, MethodClass, TrafficMetrics},
+        upstream::{Tracking, Transport, Upstream, UpstreamTier},
+    },
     repo::config::ConfigRepo,
     services::{
-        evm_rpc::{EvmRpcService, RpcMetrics},
+        chain::ChainService,
+        evm_rpc::{EvmRpcError, EvmRpcService, RpcMetrics},
+        history::HistoryService,
         monitoring::MonitoringService,
+        probe::ProbeService,
         proxy::ProxyService,
     },
-    util::controllers::{ResponseError, ResponseResult},
+    util::{
+        controllers::{ResponseError, ResponseResult},
+        stable_id, unix_timestamp,
+    },
 };
 
+const DEFAULT_HISTORY_RANGE_S: u64 = 24 * 60 * 60;
+
+#[allow(clippy::too_many_arguments)]
 #[post("/v1/chain/<chain_id>", format = "json", data = "<rpc_call>")]
 pub async fn post_chain_v1(
     chain_id: &str,
     rpc_call: Json<Value>,
     evm_rpc_service: &State<Arc<EvmRpcService>>,
-    proxy_service: &State<Arc<RwLock<ProxyService>>>,
+    chain_service: &State<Arc<ChainService>>,
+    probe_service: &State<Arc<ProbeService>>,
+    history_service: &State<Arc<HistoryService>>,
+    proxy_service: &State<Arc<ProxyService>>,
     monitoring_service: &State<Arc<MonitoringService>>,
     config_repo: &State<ConfigRepo>,
     _limitguard: RocketGovernor<'_, RateLimitGuard>,
 ) -> ResponseResult<Value> {
     monitoring_service.inc_income_requests().await;
 
-    if let None = config_repo
-        .supported_chain_ids
-        .iter()
-        .position(|val| val == chain_id)
-    {
+    if !chain_service.is_supported(chain_id).await {
         log::error!("chainId {chain_id} is not supported");
         monitoring_service.inc_error_income_requests().await;
         return Err(ResponseError {
@@ -43,7 +58,14 @@ pub async fn post_chain_v1(
         });
     }
 
-    let Some(rpcs) = evm_rpc_service.get_rpcs_for_chain_id(chain_id).await else {
+    probe_service.request_probes(chain_id).await;
+
+    let rpc_call = rpc_call.into_inner();
+    let method_class = MethodClass::from_rpc_call(&rpc_call);
+    let Some(rpcs) = evm_rpc_service
+        .get_ranked_rpcs_for_chain_id(chain_id, method_class)
+        .await
+    else {
         log::error!("failed to get rpcs for chainId {chain_id}");
         monitoring_service.inc_error_income_requests().await;
         return Err(ResponseError {
@@ -52,28 +74,54 @@ pub async fn post_chain_v1(
         });
     };
 
-    let rpc_call = rpc_call.into_inner();
-    let proxy_service = proxy_service.read().await;
-    let proxy_config = proxy_service.get_proxy();
     for i in 1..3 {
         for rpc in &rpcs {
-            let response = evm_rpc_service
-                // TODO(@kotsmile): add proxy handling
-                .rpc_request(
-                    &rpc.0,
-                    proxy_config,
-                    &rpc_call,
-                    config_repo.feed_max_timeout * i,
-                )
+            let egress = evm_rpc_service.get_egress(chain_id, &rpc.0).await;
+            let start = Instant::now();
+            let (response, proxy_config) = proxy_service
+                .request(chain_id, &rpc.0, egress, |proxy_config| {
+                    let rpc_call = &rpc_call;
+                    async move {
+                        evm_rpc_service
+                            .rpc_request(
+                                chain_id,
+                                &rpc.0.url,
+                                proxy_config.as_ref(),
+                                rpc_call,
+                                config_repo.feed_max_timeout * i,
+                            )
+                            .await
+                    }
+                })
                 .await;
+            let elapsed = start.elapsed();
+            // the upstream was not reached, proxies are already charged
+            if matches!(response, Err(EvmRpcError::Proxy(_))) {
+                continue;
+            }
+            // client errors are caused by the request, they do not count against the upstream
+            if !matches!(response, Err(EvmRpcError::Client)) {
+                evm_rpc_service
+                    .record_traffic(
+                        chain_id,
+                        &rpc.0.url,
+                        method_class,
+                        proxy_config
+                            .as_ref()
+                            .and_then(|proxy_config| proxy_config.country.as_deref()),
+                        elapsed,
+                        response.is_ok(),
+                    )
+                    .await;
+                history_service
+                    .record_request(chain_id, &rpc.0.url, elapsed, response.is_ok())
+                    .await;
+            }
 
-            match response {
-                Ok(val) => {
-                    log::info!("picked rpc: {}", rpc.0);
-                    monitoring_service.inc_success_income_requests().await;
-                    return Ok(Json(val));
-                }
-                _ => {}
+            if let Ok(val) = response {
+                log::info!("picked rpc: {}", rpc.0.url);
+                monitoring_service.inc_success_income_requests().await;
+                return Ok(Json(val));
             }
         }
     }
@@ -87,13 +135,26 @@ pub async fn post_chain_v1(
 
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct InnerMetricResponse {
+    id: String,
     rpc: String,
+    source: String,
+    tracking: Option<Tracking>,
+    tier: UpstreamTier,
+    /// Whether requests leave directly or through the proxy pool
+    egress: Egress,
     metrics: RpcMetrics,
+    traffic: Vec<TrafficMetrics>,
+    /// Live traffic through proxies by their egress country
+    countries: Vec<CountryTraffic>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct MetricsResponse {
     rpcs: Vec<InnerMetricResponse>,
+    /// WebSocket rpcs, ranked separately from http ones
+    ws_rpcs: Vec<InnerMetricResponse>,
+    /// Rpcs excluded by blocklist and allowlist rules
+    banned: Vec<BannedUpstream>,
 }
 
 #[openapi(tag = "Metrics")]
@@ -101,13 +162,9 @@ pub struct MetricsResponse {
 pub async fn get_metrics_v1(
     chain_id: &str,
     evm_rpc_service: &State<Arc<EvmRpcService>>,
-    config_repo: &State<ConfigRepo>,
+    chain_service: &State<Arc<ChainService>>,
 ) -> ResponseResult<MetricsResponse> {
-    if let None = config_repo
-        .supported_chain_ids
-        .iter()
-        .position(|val| val == chain_id)
-    {
+    if !chain_service.is_supported(chain_id).await {
         log::error!("chainId {chain_id} is not supported");
         return Err(ResponseError {
             status: Status::BadRequest,
@@ -115,21 +172,124 @@ pub async fn get_metrics_v1(
         });
     }
 
-    let Some(rpcs) = evm_rpc_service.get_rpcs_for_chain_id(chain_id).await else {
+    let Some(rpcs) = evm_rpc_service
+        .get_rpcs_for_chain_id(chain_id, Transport::Http)
+        .await
+    else {
         log::error!("failed to get rpcs for chainId {chain_id}");
         return Err(ResponseError {
             status: Status::InternalServerError,
             error: format!("No rpc provided for chainId {chain_id}"),
         });
     };
+    let ws_rpcs = evm_rpc_service
+        .get_rpcs_for_chain_id(chain_id, Transport::Ws)
+        .await
+        .unwrap_or_default();
 
+    let mut traffic = evm_rpc_service.get_traffic_for_chain_id(chain_id).await;
+    let mut country_traffic = evm_rpc_service
+        .get_country_traffic_for_chain_id(chain_id)
+        .await;
     Ok(Json(MetricsResponse {
-        rpcs: rpcs
-            .iter()
-            .map(|val| InnerMetricResponse {
-                rpc: val.0.clone(),
-                metrics: val.1.clone(),
-            })
-            .collect(),
+        rpcs: to_metric_responses(
+            chain_id,
+            rpcs,
+            &mut traffic,
+            &mut country_traffic,
+            evm_rpc_service,
+        )
+        .await,
+        ws_rpcs: to_metric_responses(
+            chain_id,
+            ws_rpcs,
+            &mut traffic,
+            &mut country_traffic,
+            evm_rpc_service,
+        )
+        .await,
+        banned: evm_rpc_service.get_banned_for_chain_id(chain_id).await,
+    }))
+}
+
+async fn to_metric_responses(
+    chain_id: &str,
+    rpcs: Vec<(Upstream, RpcMetrics)>,
+    traffic: &mut HashMap<String, Vec<TrafficMetrics>>,
+    country_traffic: &mut HashMap<String, Vec<CountryTraffic>>,
+    evm_rpc_service: &EvmRpcService,
+) -> Vec<InnerMetricResponse> {
+    let mut responses: Vec<InnerMetricResponse> = Vec::new();
+    for (upstream, metrics) in rpcs {
+        responses.push(InnerMetricResponse {
+            id: stable_id(&upstream.url),
+            traffic: traffic.remove(&upstream.url).unwrap_or_default(),
+            countries: country_traffic.remove(&upstream.url).unwrap_or_default(),
+            egress: evm_rpc_service.get_egress(chain_id, &upstream).await,
+            rpc: upstream.url,
+            source: upstream.source,
+            tracking: upstream.tracking,
+            tier: upstream.tier,
+            metrics,
+        });
+    }
+
+    responses
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct HistoryResponse {
+    id: String,
+    rpc: String,
+    from: u64,
+    to: u64,
+    points: Vec<HistoryPoint>,
+}
+
+/// History of health checks and live traffic for a single rpc. `rpc_id` is the `id` field
+/// from the metrics endpoint, `from` and `to` are unix timestamps in seconds (last 24 hours by default)
+#[openapi(tag = "Metrics")]
+#[get("/v1/chain/<chain_id>/rpc/<rpc_id>/history?<from>&<to>")]
+pub async fn get_rpc_history_v1(
+    chain_id: &str,
+    rpc_id: &str,
+    from: Option<u64>,
+    to: Option<u64>,
+    history_service: &State<Arc<HistoryService>>,
+    chain_service: &State<Arc<ChainService>>,
+) -> ResponseResult<HistoryResponse> {
+    if !chain_service.is_supported(chain_id).await {
+        log::error!("chainId {chain_id} is not supported");
+        return Err(ResponseError {
+            status: Status::BadRequest,
+            error: format!("chainId {chain_id} is not supported yet"),
+        });
+    }
+
+    let to = to.unwrap_or_else(unix_timestamp);
+    let from = from.unwrap_or(to.saturating_sub(DEFAULT_HISTORY_RANGE_S));
+    if from > to {
+        return Err(ResponseError {
+            status: Status::BadRequest,
+            error: String::from("from should not be greater than to"),
+        });
+    }
+
+    let Some((rpc, points)) = history_service
+        .get_history(chain_id, rpc_id, from, to)
+        .await
+    else {
+        return Err(ResponseError {
+            status: Status::NotFound,
+            error: format!("No history for rpc {rpc_id} on chainId {chain_id}"),
+        });
+    };
+
+    Ok(Json(HistoryResponse {
+        id: rpc_id.to_owned(),
+        rpc,
+        from,
+        to,
+        points,
     }))
 }
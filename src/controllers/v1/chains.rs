@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use rocket::{get, http::Status, serde::json::Json, State};
+use rocket_okapi::openapi;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{
+    models::{chain::ChainMetadata, upstream::Transport},
+    services::{chain::ChainService, evm_rpc::EvmRpcService},
+    util::controllers::{ResponseError, ResponseResult},
+};
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ChainResponse {
+    #[serde(flatten)]
+    metadata: ChainMetadata,
+    healthy_rpcs: usize,
+    healthy_ws_rpcs: usize,
+}
+
+async fn build_chain_response(
+    chain_id: &str,
+    chain_service: &ChainService,
+    evm_rpc_service: &EvmRpcService,
+) -> ChainResponse {
+    ChainResponse {
+        metadata: chain_service.get_chain(chain_id).await,
+        healthy_rpcs: evm_rpc_service
+            .count_healthy_rpcs(chain_id, Transport::Http)
+            .await,
+        healthy_ws_rpcs: evm_rpc_service
+            .count_healthy_rpcs(chain_id, Transport::Ws)
+            .await,
+    }
+}
+
+/// Supported and discovered chains with their metadata and number of healthy rpcs
+#[openapi(tag = "Chains")]
+#[get("/v1/chains")]
+pub async fn get_chains_v1(
+    chain_service: &State<Arc<ChainService>>,
+    evm_rpc_service: &State<Arc<EvmRpcService>>,
+) -> ResponseResult<Vec<ChainResponse>> {
+    let mut chains: Vec<ChainResponse> = Vec::new();
+    for chain_id in chain_service.get_chain_ids().await {
+        chains.push(build_chain_response(&chain_id, chain_service, evm_rpc_service).await);
+    }
+
+    Ok(Json(chains))
+}
+
+#[openapi(tag = "Chains")]
+#[get("/v1/chains/<chain_id>")]
+pub async fn get_chain_v1(
+    chain_id: &str,
+    chain_service: &State<Arc<ChainService>>,
+    evm_rpc_service: &State<Arc<EvmRpcService>>,
+) -> ResponseResult<ChainResponse> {
+    if !chain_service.is_supported(chain_id).await {
+        log::error!("chainId {chain_id} is not supported");
+        return Err(ResponseError {
+            status: Status::BadRequest,
+            error: format!("chainId {chain_id} is not supported yet"),
+        });
+    }
+
+    Ok(Json(
+        build_chain_response(chain_id, chain_service, evm_rpc_service).await,
+    ))
+}
@@ -6,7 +6,13 @@ use schemars::JsonSchema;
 use serde::Serialize;
 
 use crate::{
-    services::monitoring::MonitoringService,
+    models::{
+        bandwidth::{ChainBandwidth, ProxyBandwidth},
+        budget::BudgetStatus,
+        monitoring::ForkIncident,
+        proxy::ProxyHealth,
+    },
+    services::{monitoring::MonitoringService, proxy::ProxyService},
     util::controllers::{ResponseData, ResponseResultData},
 };
 
@@ -16,19 +22,38 @@ pub struct MonitoringResponse {
     success: u128,
     errors: u128,
     success_rate: f32,
+    fork_incidents: Vec<ForkIncident>,
+    /// Remaining budgets of paid rpcs, exhausted ones are demoted in routing
+    budgets: Vec<BudgetStatus>,
+    /// Health of the proxy pool, traffic is spread across proxies which are not dropped
+    proxies: Vec<ProxyHealth>,
+    /// Requests and bytes carried by every proxy, proxies over their daily bytes get no traffic
+    proxy_bandwidth: Vec<ProxyBandwidth>,
+    /// Requests and bytes sent through proxies per chain and upstream
+    chain_bandwidth: Vec<ChainBandwidth>,
 }
 
 #[openapi(tag = "Monitoring")]
 #[get("/v1/monitoring")]
 pub async fn get_monitoring_v1(
     monitoring_service: &State<Arc<MonitoringService>>,
+    proxy_service: &State<Arc<ProxyService>>,
 ) -> ResponseResultData<MonitoringResponse> {
     let monitoring = monitoring_service.get_monitoring().await;
+    let budgets = monitoring_service.get_budget_statuses().await;
+    let proxies = proxy_service.get_proxy_healths();
+    let proxy_bandwidth = proxy_service.get_proxy_bandwidths();
+    let chain_bandwidth = proxy_service.get_chain_bandwidths().await;
     Ok(ResponseData::build(MonitoringResponse {
         total: monitoring.income_requests,
         success: monitoring.success_income_requests,
         errors: monitoring.error_income_requests,
         success_rate: 100.0
             - (monitoring.error_income_requests as f32 / monitoring.income_requests as f32) * 100.0,
+        fork_incidents: monitoring.fork_incidents,
+        budgets,
+        proxies,
+        proxy_bandwidth,
+        chain_bandwidth,
     }))
 }
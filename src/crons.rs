@@ -1,43 +1,52 @@
-use std::{cmp::Ordering, collections::HashMap, sync::Arc, time::Duration};
+use std::sync::Arc;
 
 use anyhow::Result;
-use futures::stream::{FuturesUnordered, StreamExt};
-use rocket::tokio::sync::RwLock;
 use tokio_cron_scheduler::{Job, JobScheduler};
 
 use crate::{
+    models::upstream::Upstream,
     repo::config::ConfigRepo,
     services::{
-        evm_rpc::{EvmRpcService, RpcMetrics},
-        proxy::ProxyService,
+        chain::ChainService, evm_rpc::EvmRpcService, history::HistoryService, probe::ProbeService,
+        proxy::ProxyService, rule::RuleService, snapshot::SnapshotService,
     },
 };
 
-const BATCH_SIZE: usize = 20;
-
+#[allow(clippy::too_many_arguments)]
 pub async fn run_crons(
     evm_rpc_service: Arc<EvmRpcService>,
-    proxy_service: Arc<RwLock<ProxyService>>,
+    chain_service: Arc<ChainService>,
+    probe_service: Arc<ProbeService>,
+    history_service: Arc<HistoryService>,
+    snapshot_service: Arc<SnapshotService>,
+    rule_service: Arc<RuleService>,
+    proxy_service: Arc<ProxyService>,
     config_repo: ConfigRepo,
 ) -> Result<()> {
     let sched = JobScheduler::new().await?;
 
     {
-        let proxy_service = proxy_service.clone();
+        let chain_service = chain_service.clone();
         sched
             .add(Job::new_async("0 */5 * * * *", move |_uuid, mut _l| {
                 let evm_rpc_service = evm_rpc_service.clone();
-                let proxy_service = proxy_service.clone();
-                let supported_chain_ids = config_repo.supported_chain_ids.clone();
-                let feed_max_timeout = config_repo.feed_max_timeout.clone();
+                let chain_service = chain_service.clone();
+                let probe_service = probe_service.clone();
+                let history_service = history_service.clone();
+                let snapshot_service = snapshot_service.clone();
+                let rule_service = rule_service.clone();
+                let config_repo = config_repo.clone();
 
                 Box::pin(async move {
                     log::info!("start rpc feed cron");
                     rpc_feed_cron(
                         evm_rpc_service,
-                        proxy_service,
-                        supported_chain_ids,
-                        feed_max_timeout,
+                        chain_service,
+                        probe_service,
+                        history_service,
+                        snapshot_service,
+                        rule_service,
+                        config_repo,
                     )
                     .await;
                 })
@@ -45,13 +54,37 @@ pub async fn run_crons(
             .await?;
     }
 
+    {
+        sched
+            .add(Job::new_async("0 0 * * * *", move |_uuid, mut _l| {
+                let chain_service = chain_service.clone();
+                Box::pin(async move {
+                    log::info!("start chain registry cron");
+                    chain_service.refresh().await;
+                })
+            })?)
+            .await?;
+    }
+
+    {
+        let proxy_service = proxy_service.clone();
+        sched
+            .add(Job::new_async("30 * * * * *", move |_uuid, mut _l| {
+                let proxy_service = proxy_service.clone();
+                Box::pin(async move {
+                    proxy_reload_cron(proxy_service).await;
+                })
+            })?)
+            .await?;
+    }
+
     {
         sched
             .add(Job::new_async("0 */15 * * * *", move |_uuid, mut _l| {
                 let proxy_service = proxy_service.clone();
                 Box::pin(async move {
-                    log::info!("start proxy updater cron");
-                    proxy_updater_cron(proxy_service).await;
+                    log::info!("start proxy check cron");
+                    proxy_check_cron(proxy_service).await;
                 })
             })?)
             .await?;
@@ -62,88 +95,82 @@ pub async fn run_crons(
     Ok(())
 }
 
-pub async fn proxy_updater_cron(proxy_service: Arc<RwLock<ProxyService>>) {
+pub async fn proxy_reload_cron(proxy_service: Arc<ProxyService>) {
     let _ = proxy_service
-        .write()
+        .reload_proxies()
         .await
-        .rotate_proxy()
+        .map_err(|err| log::error!("failed to reload proxies: {err:#}"));
+}
+
+pub async fn proxy_check_cron(proxy_service: Arc<ProxyService>) {
+    let _ = proxy_service
+        .check_proxies()
         .await
-        .map_err(|err| log::error!("failed to rotate proxy: {err}"));
+        .map_err(|err| log::error!("failed to check proxies: {err}"));
 }
 
 pub async fn rpc_feed_cron(
     evm_rpc_service: Arc<EvmRpcService>,
-    proxy_service: Arc<RwLock<ProxyService>>,
-    supported_chain_ids: Vec<String>,
-    feed_max_timeout: Duration,
+    chain_service: Arc<ChainService>,
+    probe_service: Arc<ProbeService>,
+    history_service: Arc<HistoryService>,
+    snapshot_service: Arc<SnapshotService>,
+    rule_service: Arc<RuleService>,
+    config_repo: ConfigRepo,
 ) {
+    rule_service.reload().await;
+
+    let mut source_upstreams = snapshot_service.get_source_upstreams().await;
     let chain_to_rpc = evm_rpc_service
-        .fetch_rpcs()
+        .fetch_rpcs(&mut source_upstreams)
         .await
-        .map_err(|err| log::error!("failed to fetch rpcs from chainlist: {err}"));
+        .map_err(|err| log::error!("failed to fetch rpcs from sources: {err}"));
     let Ok(chain_to_rpc) = chain_to_rpc else {
         return;
     };
-
-    for chain_id in &supported_chain_ids {
+    history_service.retain(&chain_to_rpc).await;
+
+    // discovered chains are probed lazily
+    let discovered_chain_ids = chain_service.discover(chain_to_rpc.keys()).await;
+    let chain_ids = config_repo
+        .supported_chain_ids
+        .iter()
+        .map(|chain_id| (chain_id, false))
+        .chain(discovered_chain_ids.iter().map(|chain_id| (chain_id, true)));
+
+    let mut budgeted_rpcs: Vec<Upstream> = Vec::new();
+    for (chain_id, lazy) in chain_ids {
         let Some(rpcs) = chain_to_rpc.get(chain_id) else {
             log::warn!("no rpc was found for {chain_id}");
             continue;
         };
 
-        log::debug!("rpc length for {chain_id}: {}", rpcs.len());
-
-        let mut rpc_to_metric: HashMap<String, Result<RpcMetrics>> = HashMap::new();
-        for batch in rpcs.chunks(BATCH_SIZE) {
-            let proxy_service = proxy_service.read().await;
-            let proxy_config = proxy_service.get_proxy();
-            let mut futures = FuturesUnordered::new();
-            for rpc in batch {
-                let evm_rpc_service_clone = evm_rpc_service.clone();
-                let rpc_clone = rpc.to_owned();
-
-                futures.push(async move {
-                    let metric = evm_rpc_service_clone
-                        .rpc_health_check(
-                            chain_id,
-                            rpc,
-                            proxy_config,
-                            feed_max_timeout,
-                            // TODO(@kotsmile): remove hard code
-                            3,
-                        )
-                        .await;
-                    (rpc_clone, metric)
-                });
-            }
-
-            while let Some((rpc, metric)) = futures.next().await {
-                rpc_to_metric.insert(rpc, metric);
-            }
+        let (rpcs, banned) = rule_service.filter(chain_id, rpcs).await;
+        if !banned.is_empty() {
+            log::debug!("banned rpcs for {chain_id}: {}", banned.len());
         }
-
-        let mut rpcs = Vec::from_iter(rpc_to_metric.iter());
-        rpcs.sort_by(|&(_, a), &(_, b)| {
-            let Ok(a) = a else {
-                return Ordering::Greater;
-            };
-            let Ok(b) = b else {
-                return Ordering::Less;
-            };
-            b.to_score().total_cmp(&a.to_score())
-        });
-
-        let rpcs: Vec<(String, RpcMetrics)> = rpcs
-            .iter()
-            .filter_map(|&(rpc, metric)| {
-                let Ok(metric) = metric else {
-                    return None;
-                };
-
-                Some((rpc.clone(), metric.clone()))
-            })
+        evm_rpc_service
+            .set_banned_for_chain_id(chain_id, banned)
+            .await;
+
+        let privacy_policy = config_repo.privacy_policy_for(chain_id);
+        let rpcs: Vec<Upstream> = rpcs
+            .into_iter()
+            .filter(|rpc| rpc.is_allowed_by(privacy_policy))
             .collect();
 
-        evm_rpc_service.set_rpcs_for_chain_id(chain_id, rpcs).await;
+        log::debug!("rpc length for {chain_id}: {}", rpcs.len());
+
+        budgeted_rpcs.extend(rpcs.iter().filter(|rpc| rpc.budget.is_some()).cloned());
+        probe_service.sync_targets(chain_id, &rpcs, lazy).await;
+        probe_service.check_forks(chain_id).await;
+        probe_service.measure_egress(chain_id).await;
     }
+
+    evm_rpc_service.set_budgets(&budgeted_rpcs).await;
+
+    let _ = snapshot_service
+        .save(source_upstreams, discovered_chain_ids)
+        .await
+        .map_err(|err| log::error!("failed to save snapshot: {err:#}"));
 }
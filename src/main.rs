@@ -1,7 +1,7 @@
 #![warn(missing_debug_implementations, rust_2018_idioms)]
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use crons::rpc_feed_cron;
 use rocket::tokio::{sync::RwLock, task};
 
@@ -16,28 +16,57 @@ mod setup;
 mod util;
 
 use client::{
-    chainlist::ChainlistClient,
-    proxyseller::{ProxysellerClient, ProxysellerOrder},
+    chain_registry::ChainRegistryClient, chainlist::ChainlistClient, env::EnvRpcSource,
+    file::FileRpcSource, file_proxy::FileProxyProvider, proxy_checker::ProxyChecker,
+    proxy_provider::ProxyProvider, proxyseller::ProxysellerClient, registry::RegistryClient,
+    source::RpcSource, static_proxy::StaticProxyProvider,
+};
+use repo::{
+    bandwidth::BandwidthRepo, budget::BudgetRepo, cache::CacheRepo, chain::ChainRepo,
+    config::ConfigRepo, egress::EgressRepo, history::HistoryRepo, rule::RuleRepo,
+    snapshot::SnapshotRepo, traffic::TrafficRepo,
 };
-use repo::{cache::CacheRepo, config::ConfigRepo};
 use services::{
-    evm_rpc::{EvmRpcService, RpcMetrics},
-    monitoring::MonitoringService,
-    proxy::ProxyService,
+    chain::ChainService, evm_rpc::EvmRpcService, history::HistoryService,
+    monitoring::MonitoringService, probe::ProbeService, proxy::ProxyService, rule::RuleService,
+    snapshot::SnapshotService,
 };
 use setup::setup_app;
 
+#[allow(clippy::too_many_arguments)]
 async fn run_tasks(
     evm_rpc_service: Arc<EvmRpcService>,
-    proxy_service: Arc<RwLock<ProxyService>>,
+    chain_service: Arc<ChainService>,
+    probe_service: Arc<ProbeService>,
+    history_service: Arc<HistoryService>,
+    snapshot_service: Arc<SnapshotService>,
+    rule_service: Arc<RuleService>,
+    proxy_service: Arc<ProxyService>,
     config_repo: ConfigRepo,
 ) {
+    match snapshot_service.load().await {
+        Ok(()) => {
+            // discovered chains are served again once they pass the current chain filters
+            let discovered_chain_ids = chain_service
+                .discover(snapshot_service.get_discovered_chain_ids().await.iter())
+                .await;
+            let chain_ids = config_repo
+                .supported_chain_ids
+                .iter()
+                .map(|chain_id| (chain_id, false))
+                .chain(discovered_chain_ids.iter().map(|chain_id| (chain_id, true)));
+            for (chain_id, lazy) in chain_ids {
+                let rpcs = snapshot_service.get_rpcs_for_chain_id(chain_id).await;
+                probe_service.restore_targets(chain_id, rpcs, lazy).await;
+            }
+        }
+        Err(err) => log::error!("failed to load snapshot: {err:#}"),
+    }
+
     {
         let proxy_service = proxy_service.clone();
         task::spawn(async move {
             let _ = proxy_service
-                .write()
-                .await
                 .init_proxies()
                 .await
                 .map_err(|err| log::error!("failed to init proxy: {err}"));
@@ -45,48 +74,89 @@ async fn run_tasks(
     }
 
     {
-        let evm_rpc_service = evm_rpc_service.clone();
-        let proxy_service = proxy_service.clone();
-        let supported_chain_ids = config_repo.supported_chain_ids.clone();
-        let feed_max_timeout = config_repo.feed_max_timeout.clone();
+        let probe_service = probe_service.clone();
         task::spawn(async move {
-            rpc_feed_cron(
-                evm_rpc_service,
-                proxy_service,
-                supported_chain_ids,
-                feed_max_timeout,
-            )
-            .await;
+            probe_service.run().await;
         });
     }
 
-    let chain_to_rpc = evm_rpc_service
-        .fetch_rpcs()
-        .await
-        .map_err(|err| log::error!("failed to fetch rpcs from chainlist: {err}"));
-    let Ok(chain_to_rpc) = chain_to_rpc else {
-        return;
-    };
+    {
+        let chain_service = chain_service.clone();
+        task::spawn(async move {
+            chain_service.refresh().await;
+        });
+    }
 
-    for chain_id in &config_repo.supported_chain_ids {
-        let Some(rpcs) = chain_to_rpc.get(chain_id) else {
-            continue;
+    // seeds probe targets with unprobed rpcs, so requests are served before first probes finish.
+    // the app is launched meanwhile and serves the restored snapshot
+    task::spawn(rpc_feed_cron(
+        evm_rpc_service,
+        chain_service,
+        probe_service,
+        history_service,
+        snapshot_service,
+        rule_service,
+        config_repo,
+    ));
+}
+
+fn build_rpc_sources(config_repo: &ConfigRepo) -> Result<Vec<Box<dyn RpcSource>>> {
+    let mut rpc_sources: Vec<Box<dyn RpcSource>> = Vec::new();
+    for name in &config_repo.rpc_sources {
+        let rpc_source: Box<dyn RpcSource> = match name.as_str() {
+            "chainlist" => Box::new(ChainlistClient::new(config_repo.chainlist_urls.clone())),
+            "env" => Box::new(EnvRpcSource::new()),
+            "file" => Box::new(FileRpcSource::new(
+                config_repo
+                    .rpc_source_file
+                    .clone()
+                    .ok_or(anyhow!("\"RPC_SOURCE_FILE\" is required for file source"))?,
+            )),
+            "registry" => Box::new(RegistryClient::new(
+                config_repo.rpc_source_registry_url.clone().ok_or(anyhow!(
+                    "\"RPC_SOURCE_REGISTRY_URL\" is required for registry source"
+                ))?,
+            )),
+            _ => bail!("unknown rpc source {name}"),
         };
+        rpc_sources.push(rpc_source);
+    }
 
-        let mut rpcs_for_chain_id: Vec<(String, RpcMetrics)> = Vec::new();
-        for rpc in rpcs {
-            rpcs_for_chain_id.push((
-                rpc.clone(),
-                RpcMetrics {
-                    response_time_ms: 0,
-                },
-            ));
+    Ok(rpc_sources)
+}
+
+fn build_proxy_provider(config_repo: &ConfigRepo) -> Result<Option<Box<dyn ProxyProvider>>> {
+    let proxy_provider: Box<dyn ProxyProvider> = match config_repo.proxy_provider.as_str() {
+        "none" => return Ok(None),
+        "static" => {
+            if config_repo.proxy_list.is_empty() {
+                bail!("\"PROXY_LIST\" is required for static proxy provider");
+            }
+            Box::new(StaticProxyProvider::new(config_repo.proxy_list.clone()))
+        }
+        "file" => Box::new(FileProxyProvider::new(
+            config_repo.proxy_file.clone().ok_or(anyhow!(
+                "\"PROXY_FILE\" is required for file proxy provider"
+            ))?,
+        )),
+        "proxyseller" => {
+            if config_repo.proxyseller_orders.is_empty() {
+                bail!("\"PROXYSELLER_ORDERS\" is required for proxyseller proxy provider");
+            }
+            Box::new(ProxysellerClient::new(
+                config_repo.proxyseller_api_key.clone().ok_or(anyhow!(
+                    "\"PROXYSELLER_API_KEY\" is required for proxyseller proxy provider"
+                ))?,
+                config_repo.proxyseller_orders.clone(),
+                config_repo.proxyseller_protocol,
+                config_repo.proxyseller_request_timeout,
+                config_repo.proxyseller_check_timeout,
+            ))
         }
+        name => bail!("unknown proxy provider {name}"),
+    };
 
-        evm_rpc_service
-            .set_rpcs_for_chain_id(chain_id, rpcs_for_chain_id)
-            .await;
-    }
+    Ok(Some(proxy_provider))
 }
 
 #[rocket::main]
@@ -95,28 +165,74 @@ async fn main() -> Result<()> {
     env_logger::init();
 
     let cache_repo = Arc::new(RwLock::new(CacheRepo::new()));
+    let history_repo = Arc::new(RwLock::new(HistoryRepo::new()));
+    let traffic_repo = Arc::new(RwLock::new(TrafficRepo::new()));
+    let budget_repo = Arc::new(RwLock::new(BudgetRepo::new()));
+    let egress_repo = Arc::new(RwLock::new(EgressRepo::new()));
+    let bandwidth_repo = Arc::new(RwLock::new(BandwidthRepo::new()));
     let config_repo = ConfigRepo::new().context("failed to inititate config repo")?;
+    let chain_repo = Arc::new(RwLock::new(ChainRepo::new(
+        config_repo.chain_overrides_file.clone(),
+    )));
 
-    let proxyseller_client = Box::new(ProxysellerClient::new(
-        config_repo.proxyseller_api_key.clone(),
-        // TODO(@kotsmile): move orders to envs
-        vec![ProxysellerOrder(
-            String::from("mix"),
-            String::from("1973991"),
-        )],
-        3000,
-    ));
-    let chainlist_client = Box::new(ChainlistClient::new());
+    let proxy_provider =
+        build_proxy_provider(&config_repo).context("failed to build proxy provider")?;
+    let proxy_checker = ProxyChecker::new(
+        &config_repo.proxy_check_url,
+        config_repo.proxy_check_timeout,
+    )
+    .context("failed to build proxy checker")?;
+    let rpc_sources = build_rpc_sources(&config_repo).context("failed to build rpc sources")?;
 
-    let proxy_service = Arc::new(RwLock::new(ProxyService::new(proxyseller_client)));
+    let proxy_service = Arc::new(ProxyService::new(
+        proxy_provider,
+        proxy_checker,
+        bandwidth_repo.clone(),
+        config_repo.clone(),
+    ));
     let evm_rpc_service = Arc::new(EvmRpcService::new(
         cache_repo.clone(),
-        chainlist_client.clone(),
+        traffic_repo.clone(),
+        budget_repo.clone(),
+        egress_repo.clone(),
+        proxy_service.clone(),
+        rpc_sources,
+        config_repo.clone(),
+    ));
+    let monitoring_service = Arc::new(MonitoringService::new(
+        cache_repo.clone(),
+        budget_repo.clone(),
+    ));
+    let history_service = Arc::new(HistoryService::new(history_repo.clone()));
+    let probe_service = Arc::new(ProbeService::new(
+        evm_rpc_service.clone(),
+        history_service.clone(),
+        monitoring_service.clone(),
+        proxy_service.clone(),
+        config_repo.clone(),
+    ));
+    let snapshot_service = Arc::new(SnapshotService::new(
+        SnapshotRepo::new(config_repo.snapshot_path.clone()),
+        evm_rpc_service.clone(),
+        proxy_service.clone(),
+        config_repo.clone(),
+    ));
+    let rule_service = Arc::new(RuleService::new(RuleRepo::new(
+        config_repo.upstream_rules_file.clone(),
+    )));
+    let chain_service = Arc::new(ChainService::new(
+        chain_repo.clone(),
+        ChainRegistryClient::new(config_repo.chain_registry_url.clone()),
+        config_repo.clone(),
     ));
-    let monitoring_service = Arc::new(MonitoringService::new(cache_repo.clone()));
 
     run_tasks(
         evm_rpc_service.clone(),
+        chain_service.clone(),
+        probe_service.clone(),
+        history_service.clone(),
+        snapshot_service.clone(),
+        rule_service.clone(),
         proxy_service.clone(),
         config_repo.clone(),
     )
@@ -124,6 +240,11 @@ async fn main() -> Result<()> {
 
     crons::run_crons(
         evm_rpc_service.clone(),
+        chain_service.clone(),
+        probe_service.clone(),
+        history_service.clone(),
+        snapshot_service.clone(),
+        rule_service.clone(),
         proxy_service.clone(),
         config_repo.clone(),
     )
@@ -131,6 +252,9 @@ async fn main() -> Result<()> {
 
     setup_app(
         evm_rpc_service.clone(),
+        chain_service.clone(),
+        probe_service.clone(),
+        history_service.clone(),
         proxy_service.clone(),
         monitoring_service.clone(),
         config_repo.clone(),
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// bodies of json rpc requests and responses, headers and tunnel overhead are not counted
+#[derive(Debug, Clone, Copy, Default, JsonSchema, Serialize)]
+pub struct Bandwidth {
+    pub requests: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+impl Bandwidth {
+    pub fn bytes(&self) -> u64 {
+        self.request_bytes + self.response_bytes
+    }
+}
+
+// updated by concurrent requests without a lock
+#[derive(Debug, Default)]
+pub struct BandwidthCounter {
+    requests: AtomicU64,
+    request_bytes: AtomicU64,
+    response_bytes: AtomicU64,
+}
+
+impl BandwidthCounter {
+    pub fn record(&self, request_bytes: u64, response_bytes: u64) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.request_bytes
+            .fetch_add(request_bytes, Ordering::Relaxed);
+        self.response_bytes
+            .fetch_add(response_bytes, Ordering::Relaxed);
+    }
+
+    pub fn load(&self) -> Bandwidth {
+        Bandwidth {
+            requests: self.requests.load(Ordering::Relaxed),
+            request_bytes: self.request_bytes.load(Ordering::Relaxed),
+            response_bytes: self.response_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// bytes of a proxy in the current day, kept in the snapshot between restarts
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ProxySpend {
+    pub day: u64,
+    pub daily_bytes: u64,
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct ProxyBandwidth {
+    /// Proxy address without credentials
+    pub proxy: String,
+    /// Traffic carried by the proxy since the start
+    pub total: Bandwidth,
+    /// Request and response bytes of the current UTC day
+    pub daily_bytes: u64,
+    pub daily_limit: Option<u64>,
+    /// Exhausted proxies get no traffic until the next UTC day
+    pub exhausted: bool,
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct UpstreamBandwidth {
+    pub rpc: String,
+    pub total: Bandwidth,
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct ChainBandwidth {
+    pub chain_id: String,
+    /// Traffic sent to upstreams of the chain through proxies
+    pub total: Bandwidth,
+    pub upstreams: Vec<UpstreamBandwidth>,
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::util::{unix_day, unix_month};
+
+// cost of methods missing in `method_costs` for compute unit budgets
+const DEFAULT_METHOD_COST: u64 = 1;
+const WILDCARD_METHOD: &str = "*";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetUnit {
+    #[default]
+    Requests,
+    ComputeUnits,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct Budget {
+    #[serde(default)]
+    pub unit: BudgetUnit,
+    pub daily: Option<u64>,
+    pub monthly: Option<u64>,
+    /// Compute units per method, `*` matches every other method
+    #[serde(default)]
+    pub method_costs: HashMap<String, u64>,
+}
+
+impl Budget {
+    // batch calls are charged for every call in the batch
+    pub fn cost(&self, rpc_call: &Value) -> u64 {
+        let calls = match rpc_call {
+            Value::Array(calls) => calls.iter().collect(),
+            call => vec![call],
+        };
+
+        match self.unit {
+            BudgetUnit::Requests => calls.len() as u64,
+            BudgetUnit::ComputeUnits => calls
+                .into_iter()
+                .map(|call| {
+                    call.get("method")
+                        .and_then(Value::as_str)
+                        .and_then(|method| self.method_costs.get(method))
+                        .or(self.method_costs.get(WILDCARD_METHOD))
+                        .copied()
+                        .unwrap_or(DEFAULT_METHOD_COST)
+                })
+                .sum(),
+        }
+    }
+}
+
+// units spent in the current day and month, kept in the snapshot between restarts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetSpend {
+    pub day: u64,
+    pub daily_used: u64,
+    pub month: u64,
+    pub monthly_used: u64,
+}
+
+impl BudgetSpend {
+    // units of past periods count as not spent
+    pub fn daily_used_at(&self, timestamp: u64) -> u64 {
+        if unix_day(timestamp) == self.day {
+            self.daily_used
+        } else {
+            0
+        }
+    }
+
+    pub fn monthly_used_at(&self, timestamp: u64) -> u64 {
+        if unix_month(timestamp) == self.month {
+            self.monthly_used
+        } else {
+            0
+        }
+    }
+
+    pub fn spend(&mut self, units: u64, timestamp: u64) {
+        self.daily_used = self.daily_used_at(timestamp) + units;
+        self.monthly_used = self.monthly_used_at(timestamp) + units;
+        self.day = unix_day(timestamp);
+        self.month = unix_month(timestamp);
+    }
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct BudgetStatus {
+    pub rpc: String,
+    pub unit: BudgetUnit,
+    pub daily_limit: Option<u64>,
+    pub daily_remaining: Option<u64>,
+    pub monthly_limit: Option<u64>,
+    pub monthly_remaining: Option<u64>,
+    pub exhausted: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-31 12:00:00
+    const TIMESTAMP: u64 = 1_706_702_400;
+    const DAY: u64 = 86_400;
+
+    #[test]
+    fn resets_spend_of_past_periods() {
+        let mut spend = BudgetSpend::default();
+        spend.spend(3, TIMESTAMP);
+        spend.spend(2, TIMESTAMP);
+
+        assert_eq!(spend.daily_used_at(TIMESTAMP), 5);
+        assert_eq!(spend.monthly_used_at(TIMESTAMP), 5);
+        // the next day is in the next month as well
+        assert_eq!(spend.daily_used_at(TIMESTAMP + DAY), 0);
+        assert_eq!(spend.monthly_used_at(TIMESTAMP + DAY), 0);
+
+        spend.spend(1, TIMESTAMP + DAY);
+        assert_eq!(spend.daily_used_at(TIMESTAMP + DAY), 1);
+        assert_eq!(spend.monthly_used_at(TIMESTAMP + DAY), 1);
+        spend.spend(1, TIMESTAMP + 2 * DAY);
+        assert_eq!(spend.daily_used_at(TIMESTAMP + 2 * DAY), 1);
+        assert_eq!(spend.monthly_used_at(TIMESTAMP + 2 * DAY), 2);
+    }
+}
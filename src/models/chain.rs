@@ -0,0 +1,73 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct NativeCurrency {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct Explorer {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct ChainMetadata {
+    pub chain_id: String,
+    pub name: Option<String>,
+    pub short_name: Option<String>,
+    pub native_currency: Option<NativeCurrency>,
+    pub block_time_ms: Option<u64>,
+    pub explorers: Vec<Explorer>,
+    /// Number of blocks after which a block is considered final
+    pub finality_depth: Option<u64>,
+}
+
+impl ChainMetadata {
+    pub fn new(chain_id: &str) -> Self {
+        Self {
+            chain_id: chain_id.to_owned(),
+            name: None,
+            short_name: None,
+            native_currency: None,
+            block_time_ms: None,
+            explorers: Vec::new(),
+            finality_depth: None,
+        }
+    }
+
+    pub fn apply(&mut self, chain_override: &ChainOverride) {
+        if let Some(name) = &chain_override.name {
+            self.name = Some(name.clone());
+        }
+        if let Some(short_name) = &chain_override.short_name {
+            self.short_name = Some(short_name.clone());
+        }
+        if let Some(native_currency) = &chain_override.native_currency {
+            self.native_currency = Some(native_currency.clone());
+        }
+        if let Some(block_time_ms) = chain_override.block_time_ms {
+            self.block_time_ms = Some(block_time_ms);
+        }
+        if let Some(explorers) = &chain_override.explorers {
+            self.explorers = explorers.clone();
+        }
+        if let Some(finality_depth) = chain_override.finality_depth {
+            self.finality_depth = Some(finality_depth);
+        }
+    }
+}
+
+// configured values which replace the fetched ones field by field
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChainOverride {
+    pub name: Option<String>,
+    pub short_name: Option<String>,
+    pub native_currency: Option<NativeCurrency>,
+    pub block_time_ms: Option<u64>,
+    pub explorers: Option<Vec<Explorer>>,
+    pub finality_depth: Option<u64>,
+}
@@ -0,0 +1,139 @@
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{bail, Error};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// how requests leave for an upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Egress {
+    Direct,
+    // through the proxy pool, requests go directly when there is no pool
+    Proxied,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EgressPolicy {
+    // egress is picked by measurements of every upstream
+    Auto,
+    Direct,
+    Proxied,
+}
+
+impl EgressPolicy {
+    pub fn pinned(&self) -> Option<Egress> {
+        match self {
+            Self::Auto => None,
+            Self::Direct => Some(Egress::Direct),
+            Self::Proxied => Some(Egress::Proxied),
+        }
+    }
+}
+
+impl FromStr for EgressPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "direct" => Ok(Self::Direct),
+            "proxied" => Ok(Self::Proxied),
+            _ => bail!("unknown egress policy {s}"),
+        }
+    }
+}
+
+impl Egress {
+    // latencies are the ones of successful measurements,
+    // proxied is preferred to spread traffic across ips, direct is picked when the proxy pool
+    // fails the upstream or is slower than direct more than `latency_ratio` times
+    pub fn choose(
+        direct: Option<Duration>,
+        proxied: Option<Duration>,
+        latency_ratio: f32,
+    ) -> Option<Egress> {
+        match (direct, proxied) {
+            (Some(direct), Some(proxied)) => {
+                if proxied.as_secs_f32() > direct.as_secs_f32() * latency_ratio {
+                    Some(Egress::Direct)
+                } else {
+                    Some(Egress::Proxied)
+                }
+            }
+            (Some(_), None) => Some(Egress::Direct),
+            (None, Some(_)) => Some(Egress::Proxied),
+            (None, None) => None,
+        }
+    }
+}
+
+// egress countries for an upstream, proxies with unknown country are never preferred
+#[derive(Debug, Clone, Default, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct GeoPolicy {
+    #[serde(default)]
+    pub prefer: Vec<String>,
+    #[serde(default)]
+    pub forbid: Vec<String>,
+}
+
+impl GeoPolicy {
+    pub fn allows(&self, country: Option<&str>) -> bool {
+        country.is_none_or(|country| {
+            !self
+                .forbid
+                .iter()
+                .any(|forbidden| forbidden.eq_ignore_ascii_case(country))
+        })
+    }
+
+    pub fn prefers(&self, country: Option<&str>) -> bool {
+        country.is_some_and(|country| {
+            self.prefer
+                .iter()
+                .any(|preferred| preferred.eq_ignore_ascii_case(country))
+        })
+    }
+}
+
+// comma separated countries, forbidden ones are prefixed with `!`, e.g. `DE,NL,!US`
+impl FromStr for GeoPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut geo_policy = Self::default();
+        for country in s.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            let (countries, country) = match country.strip_prefix('!') {
+                Some(country) => (&mut geo_policy.forbid, country),
+                None => (&mut geo_policy.prefer, country),
+            };
+            if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                bail!("invalid country code {country}");
+            }
+            countries.push(country.to_uppercase());
+        }
+
+        Ok(geo_policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_geo_policy() {
+        let geo_policy: GeoPolicy = " de, NL ,!us,".parse().unwrap();
+        assert_eq!(geo_policy.prefer, vec!["DE", "NL"]);
+        assert_eq!(geo_policy.forbid, vec!["US"]);
+        assert!(geo_policy.prefers(Some("nl")));
+        assert!(!geo_policy.allows(Some("US")));
+        assert!(geo_policy.allows(None));
+        assert!(!geo_policy.prefers(None));
+
+        assert_eq!("".parse::<GeoPolicy>().unwrap(), GeoPolicy::default());
+        assert!("DEU".parse::<GeoPolicy>().is_err());
+        assert!("!".parse::<GeoPolicy>().is_err());
+        assert!("D1".parse::<GeoPolicy>().is_err());
+    }
+}
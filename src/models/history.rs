@@ -0,0 +1,15 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, JsonSchema, Serialize)]
+pub struct HistoryPoint {
+    pub timestamp: u64,
+    pub resolution_s: u64,
+    pub checks: u64,
+    pub failed_checks: u64,
+    pub avg_response_time_ms: Option<u128>,
+    pub avg_reliability: Option<f32>,
+    pub requests: u64,
+    pub failed_requests: u64,
+    pub avg_request_time_ms: Option<u128>,
+}
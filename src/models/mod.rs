@@ -1,2 +1,11 @@
-pub mod proxy;
+pub mod bandwidth;
+pub mod budget;
+pub mod chain;
+pub mod egress;
+pub mod history;
 pub mod monitoring;
+pub mod proxy;
+pub mod rule;
+pub mod snapshot;
+pub mod traffic;
+pub mod upstream;
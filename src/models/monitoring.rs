@@ -1,11 +1,29 @@
 use schemars::JsonSchema;
 use serde::Serialize;
 
-#[derive(Debug, Clone, Copy, JsonSchema, Serialize)]
+const MAX_FORK_INCIDENTS: usize = 100;
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct ForkBranch {
+    pub hash: String,
+    pub rpcs: Vec<String>,
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct ForkIncident {
+    pub chain_id: String,
+    pub height: u64,
+    pub timestamp: u64,
+    pub branches: Vec<ForkBranch>,
+    pub quarantined_rpcs: Vec<String>,
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
 pub struct Monitoring {
     pub income_requests: u128,
     pub success_income_requests: u128,
     pub error_income_requests: u128,
+    pub fork_incidents: Vec<ForkIncident>,
 }
 
 impl Monitoring {
@@ -14,6 +32,14 @@ impl Monitoring {
             income_requests: 0,
             success_income_requests: 0,
             error_income_requests: 0,
+            fork_incidents: Vec::new(),
+        }
+    }
+
+    pub fn push_fork_incident(&mut self, incident: ForkIncident) {
+        if self.fork_incidents.len() >= MAX_FORK_INCIDENTS {
+            self.fork_incidents.remove(0);
         }
+        self.fork_incidents.push(incident);
     }
 }
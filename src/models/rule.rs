@@ -0,0 +1,287 @@
+use regex::Regex;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::models::upstream::Upstream;
+
+const REGEX_PREFIX: &str = "re:";
+
+// glob pattern with `*` and `?` wildcards, or a regex when prefixed with `re:`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn is_match(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if let Some(regex) = value.strip_prefix(REGEX_PREFIX) {
+            return Regex::new(regex).map(Self);
+        }
+
+        let glob: String = value
+            .chars()
+            .map(|char| match char {
+                '*' => String::from(".*"),
+                '?' => String::from("."),
+                char => regex::escape(&char.to_string()),
+            })
+            .collect();
+        Regex::new(&format!("(?i)^{glob}$")).map(Self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Block,
+    // once a chain has an allow rule only matching upstreams are used for it
+    Allow,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpstreamRule {
+    pub action: RuleAction,
+    // rpc source which found the upstream, e.g. `chainlist`
+    pub source: Option<Pattern>,
+    // the whole url, including scheme and path
+    pub url: Option<Pattern>,
+    pub host: Option<Pattern>,
+    pub path: Option<Pattern>,
+    pub chain: Option<Pattern>,
+    pub reason: Option<String>,
+    pub expires_at: Option<u64>,
+}
+
+impl UpstreamRule {
+    pub fn block_url(source: &str, url: &str, reason: &str) -> Self {
+        Self {
+            action: RuleAction::Block,
+            source: Pattern::try_from(source.to_owned()).ok(),
+            url: Pattern::try_from(url.to_owned()).ok(),
+            host: None,
+            path: None,
+            chain: None,
+            reason: Some(reason.to_owned()),
+            expires_at: None,
+        }
+    }
+
+    fn is_active(&self, timestamp: u64) -> bool {
+        self.expires_at
+            .is_none_or(|expires_at| expires_at > timestamp)
+    }
+
+    fn applies_to_chain(&self, chain_id: &str) -> bool {
+        self.chain
+            .as_ref()
+            .is_none_or(|chain| chain.is_match(chain_id))
+    }
+
+    fn matches(&self, chain_id: &str, upstream: &Upstream, url: &Url) -> bool {
+        self.applies_to_chain(chain_id)
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|source| source.is_match(&upstream.source))
+            && self
+                .url
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&upstream.url))
+            && self
+                .host
+                .as_ref()
+                .is_none_or(|host| host.is_match(url.host_str().unwrap_or_default()))
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|path| path.is_match(url.path()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpstreamRules {
+    #[serde(default)]
+    pub rules: Vec<UpstreamRule>,
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct BannedUpstream {
+    pub rpc: String,
+    pub source: String,
+    pub reason: String,
+    /// Unix timestamp in seconds, bans without it are permanent
+    pub expires_at: Option<u64>,
+}
+
+// block rules win over allow rules
+pub fn check_upstream(
+    rules: &[UpstreamRule],
+    chain_id: &str,
+    upstream: &Upstream,
+    timestamp: u64,
+) -> Option<BannedUpstream> {
+    let ban = |reason: String, expires_at: Option<u64>| BannedUpstream {
+        rpc: upstream.url.clone(),
+        source: upstream.source.clone(),
+        reason,
+        expires_at,
+    };
+    let Ok(url) = Url::parse(&upstream.url) else {
+        return Some(ban(String::from("invalid url"), None));
+    };
+
+    let rules = rules.iter().filter(|rule| rule.is_active(timestamp));
+    if let Some(rule) = rules
+        .clone()
+        .find(|rule| rule.action == RuleAction::Block && rule.matches(chain_id, upstream, &url))
+    {
+        let reason = rule
+            .reason
+            .clone()
+            .unwrap_or_else(|| String::from("blocked by rule"));
+        return Some(ban(reason, rule.expires_at));
+    }
+
+    let mut allow_rules = rules
+        .filter(|rule| rule.action == RuleAction::Allow && rule.applies_to_chain(chain_id))
+        .peekable();
+    if allow_rules.peek().is_some()
+        && !allow_rules.any(|rule| rule.matches(chain_id, upstream, &url))
+    {
+        return Some(ban(String::from("not in allowlist"), None));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const TIMESTAMP: u64 = 1_704_067_200;
+
+    fn rule(value: serde_json::Value) -> UpstreamRule {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn check(rules: &[UpstreamRule], chain_id: &str, url: &str, source: &str) -> Option<String> {
+        check_upstream(rules, chain_id, &Upstream::new(url, source), TIMESTAMP)
+            .map(|ban| ban.reason)
+    }
+
+    #[test]
+    fn matches_glob_and_regex_patterns() {
+        let glob = Pattern::try_from(String::from("*.Example.io")).unwrap();
+        assert!(glob.is_match("rpc.example.io"));
+        assert!(!glob.is_match("rpc.example.io.evil"));
+        assert!(!glob.is_match("rpc-example.io"));
+        let single = Pattern::try_from(String::from("rpc?.io")).unwrap();
+        assert!(single.is_match("rpc1.io"));
+        assert!(!single.is_match("rpc12.io"));
+
+        // regexes are not anchored and keep their case
+        let regex = Pattern::try_from(String::from("re:^rpc[0-9]+\\.")).unwrap();
+        assert!(regex.is_match("rpc12.example.io"));
+        assert!(!regex.is_match("RPC12.example.io"));
+        assert!(Pattern::try_from(String::from("re:(")).is_err());
+    }
+
+    #[test]
+    fn blocks_win_over_allows() {
+        let rules = [
+            rule(json!({ "action": "allow", "host": "*.example.io" })),
+            rule(json!({ "action": "block", "host": "bad.example.io", "reason": "bad" })),
+        ];
+
+        assert_eq!(check(&rules, "1", "https://good.example.io", "file"), None);
+        assert_eq!(
+            check(&rules, "1", "https://bad.example.io", "file").as_deref(),
+            Some("bad")
+        );
+    }
+
+    #[test]
+    fn allowlists_only_their_chains() {
+        let rules = [rule(json!({
+            "action": "allow",
+            "chain": "1",
+            "host": "*.example.io",
+            "path": "/v1/*",
+        }))];
+
+        assert_eq!(
+            check(&rules, "1", "https://rpc.example.io/v1/eth", "chainlist"),
+            None
+        );
+        assert_eq!(
+            check(&rules, "1", "https://rpc.example.io/v2/eth", "chainlist").as_deref(),
+            Some("not in allowlist")
+        );
+        assert_eq!(
+            check(&rules, "1", "https://rpc.other.io/v1/eth", "chainlist").as_deref(),
+            Some("not in allowlist")
+        );
+        assert_eq!(
+            check(&rules, "10", "https://rpc.other.io", "chainlist"),
+            None
+        );
+    }
+
+    #[test]
+    fn skips_expired_rules() {
+        let rules = [rule(json!({
+            "action": "block",
+            "host": "rpc.example.io",
+            "expires_at": TIMESTAMP,
+        }))];
+
+        assert_eq!(check(&rules, "1", "https://rpc.example.io", "file"), None);
+    }
+
+    #[test]
+    fn blocks_urls_of_the_given_source_only() {
+        let rules = [UpstreamRule::block_url(
+            "chainlist",
+            "*polysplit*",
+            "polysplit endpoint",
+        )];
+
+        assert_eq!(
+            check(
+                &rules,
+                "1",
+                "https://rpc.polysplit.cloud/v1/chain/1",
+                "chainlist"
+            )
+            .as_deref(),
+            Some("polysplit endpoint")
+        );
+        assert_eq!(
+            check(&rules, "1", "https://rpc.example.io/polysplit", "chainlist").as_deref(),
+            Some("polysplit endpoint")
+        );
+        assert_eq!(
+            check(
+                &rules,
+                "1",
+                "https://rpc.polysplit.cloud/v1/chain/1",
+                "file"
+            ),
+            None
+        );
+        assert_eq!(
+            check(&rules, "1", "https://rpc.example.io", "chainlist"),
+            None
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::source::ChainToUpstreams,
+    models::{bandwidth::ProxySpend, budget::BudgetSpend, upstream::Upstream},
+    services::evm_rpc::RpcMetrics,
+};
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// last known good state, used until the first feed and probes complete after boot
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub saved_at: u64,
+    // upstreams of the latest successful fetch from every rpc source
+    pub source_upstreams: HashMap<String, ChainToUpstreams>,
+    pub chain_id_to_rpcs: HashMap<String, Vec<(Upstream, RpcMetrics)>>,
+    // chains found by rpc sources in all chains mode, restored as lazy ones
+    #[serde(default)]
+    pub discovered_chain_ids: Vec<String>,
+    // spent budgets of paid rpcs, so restarts do not reset them
+    #[serde(default)]
+    pub budget_spends: HashMap<String, BudgetSpend>,
+    // daily bytes of proxies by address, so restarts do not reset their budgets
+    #[serde(default)]
+    pub proxy_spends: HashMap<String, ProxySpend>,
+}
@@ -0,0 +1,98 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::util::ewma;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MethodClass {
+    Call,
+    Logs,
+    Other,
+}
+
+impl MethodClass {
+    pub fn from_rpc_call(rpc_call: &Value) -> Self {
+        let call = match rpc_call {
+            Value::Array(calls) => calls.first(),
+            call => Some(call),
+        };
+
+        match call
+            .and_then(|call| call.get("method"))
+            .and_then(Value::as_str)
+        {
+            Some("eth_call" | "eth_estimateGas") => Self::Call,
+            Some("eth_getLogs") => Self::Logs,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, JsonSchema, Serialize)]
+pub struct TrafficMetrics {
+    pub method_class: MethodClass,
+    pub requests: u64,
+    /// Exponentially weighted moving average of successful requests latency
+    pub latency_ms: f32,
+    /// Exponentially weighted moving average of request outcomes, from 0.0 to 1.0
+    pub success_rate: f32,
+}
+
+impl TrafficMetrics {
+    pub fn new(method_class: MethodClass) -> Self {
+        Self {
+            method_class,
+            requests: 0,
+            latency_ms: 0.0,
+            success_rate: 1.0,
+        }
+    }
+
+    pub fn record(&mut self, latency_ms: f32, success: bool, alpha: f32) {
+        let outcome = if success { 1.0 } else { 0.0 };
+        self.success_rate = ewma(self.success_rate, outcome, alpha, self.requests > 0);
+        if success {
+            self.latency_ms = ewma(self.latency_ms, latency_ms, alpha, self.latency_ms != 0.0);
+        }
+
+        self.requests += 1;
+    }
+
+    pub fn score(&self) -> f32 {
+        self.success_rate / self.latency_ms.max(1.0)
+    }
+}
+
+#[derive(Debug, Clone, JsonSchema, Serialize)]
+pub struct CountryTraffic {
+    /// Egress country of the proxies which carried the requests
+    pub country: String,
+    pub requests: u64,
+    /// Exponentially weighted moving average of successful requests latency
+    pub latency_ms: f32,
+    /// Exponentially weighted moving average of request outcomes, from 0.0 to 1.0
+    pub success_rate: f32,
+}
+
+impl CountryTraffic {
+    pub fn new(country: &str) -> Self {
+        Self {
+            country: country.to_owned(),
+            requests: 0,
+            latency_ms: 0.0,
+            success_rate: 1.0,
+        }
+    }
+
+    pub fn record(&mut self, latency_ms: f32, success: bool, alpha: f32) {
+        let outcome = if success { 1.0 } else { 0.0 };
+        self.success_rate = ewma(self.success_rate, outcome, alpha, self.requests > 0);
+        if success {
+            self.latency_ms = ewma(self.latency_ms, latency_ms, alpha, self.latency_ms != 0.0);
+        }
+
+        self.requests += 1;
+    }
+}
@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Error};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    budget::Budget,
+    egress::{Egress, GeoPolicy},
+};
+
+// rpcs of the only third party source, the other sources are configured by the operator
+const UNTRUSTED_SOURCE: &str = "chainlist";
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, JsonSchema, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Tracking {
+    None,
+    Limited,
+    Yes,
+}
+
+impl FromStr for Tracking {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "limited" => Ok(Self::Limited),
+            "yes" => Ok(Self::Yes),
+            _ => bail!("unknown tracking level {s}"),
+        }
+    }
+}
+
+// the most tracking which is allowed for rpcs receiving user traffic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivacyPolicy {
+    Any,
+    Limited,
+    None,
+}
+
+impl FromStr for PrivacyPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(Self::Any),
+            "limited" => Ok(Self::Limited),
+            "none" => Ok(Self::None),
+            _ => bail!("unknown privacy policy {s}"),
+        }
+    }
+}
+
+// routing drains every tier before falling back to the next one
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, JsonSchema, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum UpstreamTier {
+    // own or paid nodes
+    Primary,
+    // vetted public nodes
+    Secondary,
+    Overflow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Http,
+    Ws,
+}
+
+impl Transport {
+    pub const ALL: [Transport; 2] = [Transport::Http, Transport::Ws];
+
+    pub fn from_url(url: &str) -> Self {
+        if url.starts_with("wss://") || url.starts_with("ws://") {
+            Self::Ws
+        } else {
+            Self::Http
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct Upstream {
+    pub url: String,
+    pub source: String,
+    pub tracking: Option<Tracking>,
+    pub transport: Transport,
+    pub tier: UpstreamTier,
+    pub budget: Option<Budget>,
+    // operator pinned egress, measured when not set
+    pub egress: Option<Egress>,
+    // operator egress countries, the ones of the chain are used when not set
+    pub geo: Option<GeoPolicy>,
+}
+
+impl Upstream {
+    pub fn new(url: &str, source: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            source: source.to_owned(),
+            tracking: None,
+            transport: Transport::from_url(url),
+            tier: UpstreamTier::Overflow,
+            budget: None,
+            egress: None,
+            geo: None,
+        }
+    }
+
+    pub fn with_tier(mut self, tier: UpstreamTier) -> Self {
+        self.tier = tier;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: Option<Budget>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn with_egress(mut self, egress: Option<Egress>) -> Self {
+        self.egress = egress;
+        self
+    }
+
+    pub fn with_geo(mut self, geo: Option<GeoPolicy>) -> Self {
+        self.geo = geo;
+        self
+    }
+
+    pub fn with_tracking(mut self, tracking: Option<Tracking>) -> Self {
+        self.tracking = tracking;
+        self
+    }
+
+    // declared tracking is always respected, unknown one is trusted for operator sources
+    // while chainlist rpcs without tracking metadata are allowed only by `any` policy
+    pub fn is_allowed_by(&self, privacy_policy: PrivacyPolicy) -> bool {
+        let Some(tracking) = self.tracking else {
+            return privacy_policy == PrivacyPolicy::Any || self.source != UNTRUSTED_SOURCE;
+        };
+        match privacy_policy {
+            PrivacyPolicy::Any => true,
+            PrivacyPolicy::Limited => tracking <= Tracking::Limited,
+            PrivacyPolicy::None => tracking == Tracking::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICIES: [PrivacyPolicy; 3] = [
+        PrivacyPolicy::Any,
+        PrivacyPolicy::Limited,
+        PrivacyPolicy::None,
+    ];
+
+    #[test]
+    fn trusts_unknown_tracking_of_operator_sources() {
+        for source in ["env", "file", "registry"] {
+            let upstream = Upstream::new("https://rpc.example", source);
+            for policy in POLICIES {
+                assert!(upstream.is_allowed_by(policy), "{source} {policy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn filters_unknown_tracking_of_chainlist() {
+        let upstream = Upstream::new("https://rpc.example", "chainlist");
+        assert!(upstream.is_allowed_by(PrivacyPolicy::Any));
+        assert!(!upstream.is_allowed_by(PrivacyPolicy::Limited));
+        assert!(!upstream.is_allowed_by(PrivacyPolicy::None));
+    }
+
+    #[test]
+    fn respects_declared_tracking_of_every_source() {
+        for source in ["env", "file", "registry", "chainlist"] {
+            let upstream = |tracking| {
+                Upstream::new("https://rpc.example", source).with_tracking(Some(tracking))
+            };
+            for policy in POLICIES {
+                assert!(upstream(Tracking::None).is_allowed_by(policy));
+            }
+            assert!(upstream(Tracking::Limited).is_allowed_by(PrivacyPolicy::Any));
+            assert!(upstream(Tracking::Limited).is_allowed_by(PrivacyPolicy::Limited));
+            assert!(!upstream(Tracking::Limited).is_allowed_by(PrivacyPolicy::None));
+            assert!(upstream(Tracking::Yes).is_allowed_by(PrivacyPolicy::Any));
+            assert!(!upstream(Tracking::Yes).is_allowed_by(PrivacyPolicy::Limited));
+            assert!(!upstream(Tracking::Yes).is_allowed_by(PrivacyPolicy::None));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::models::bandwidth::{Bandwidth, BandwidthCounter, ChainBandwidth, UpstreamBandwidth};
+
+// counters are created once per upstream and then updated under a read lock
+pub struct BandwidthRepo {
+    chain_id_to_rpc_to_counter: HashMap<String, HashMap<String, Arc<BandwidthCounter>>>,
+}
+
+impl BandwidthRepo {
+    pub fn new() -> Self {
+        Self {
+            chain_id_to_rpc_to_counter: HashMap::new(),
+        }
+    }
+
+    pub fn get_counter(&self, chain_id: &str, rpc: &str) -> Option<Arc<BandwidthCounter>> {
+        self.chain_id_to_rpc_to_counter
+            .get(chain_id)
+            .and_then(|rpc_to_counter| rpc_to_counter.get(rpc))
+            .cloned()
+    }
+
+    pub fn add_counter(&mut self, chain_id: &str, rpc: &str) -> Arc<BandwidthCounter> {
+        self.chain_id_to_rpc_to_counter
+            .entry(chain_id.to_owned())
+            .or_default()
+            .entry(rpc.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    pub fn get_chain_bandwidths(&self) -> Vec<ChainBandwidth> {
+        let mut chain_bandwidths: Vec<ChainBandwidth> = self
+            .chain_id_to_rpc_to_counter
+            .iter()
+            .map(|(chain_id, rpc_to_counter)| {
+                let mut total = Bandwidth::default();
+                let mut upstreams: Vec<UpstreamBandwidth> = rpc_to_counter
+                    .iter()
+                    .map(|(rpc, counter)| {
+                        let bandwidth = counter.load();
+                        total.requests += bandwidth.requests;
+                        total.request_bytes += bandwidth.request_bytes;
+                        total.response_bytes += bandwidth.response_bytes;
+                        UpstreamBandwidth {
+                            rpc: rpc.clone(),
+                            total: bandwidth,
+                        }
+                    })
+                    .collect();
+                upstreams.sort_by_key(|upstream| std::cmp::Reverse(upstream.total.bytes()));
+
+                ChainBandwidth {
+                    chain_id: chain_id.clone(),
+                    total,
+                    upstreams,
+                }
+            })
+            .collect();
+        chain_bandwidths.sort_by(|a, b| a.chain_id.cmp(&b.chain_id));
+        chain_bandwidths
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::models::budget::{Budget, BudgetSpend, BudgetStatus};
+
+// spends are kept per period, so lookups only need a read lock
+pub struct BudgetRepo {
+    rpc_to_budget: HashMap<String, Budget>,
+    rpc_to_spend: HashMap<String, BudgetSpend>,
+}
+
+impl BudgetRepo {
+    pub fn new() -> Self {
+        Self {
+            rpc_to_budget: HashMap::new(),
+            rpc_to_spend: HashMap::new(),
+        }
+    }
+
+    // spent units are kept when the budget of an rpc changes
+    pub fn set_budgets(&mut self, rpc_to_budget: HashMap<String, Budget>) {
+        self.rpc_to_spend
+            .retain(|rpc, _| rpc_to_budget.contains_key(rpc));
+        self.rpc_to_budget = rpc_to_budget;
+    }
+
+    pub fn spend(&mut self, rpc: &str, rpc_call: &Value, timestamp: u64) {
+        let Some(budget) = self.rpc_to_budget.get(rpc) else {
+            return;
+        };
+
+        self.rpc_to_spend
+            .entry(rpc.to_owned())
+            .or_default()
+            .spend(budget.cost(rpc_call), timestamp);
+    }
+
+    pub fn is_exhausted(&self, rpc: &str, timestamp: u64) -> bool {
+        let Some(budget) = self.rpc_to_budget.get(rpc) else {
+            return false;
+        };
+        let Some(spend) = self.rpc_to_spend.get(rpc) else {
+            return false;
+        };
+
+        budget
+            .daily
+            .is_some_and(|daily| spend.daily_used_at(timestamp) >= daily)
+            || budget
+                .monthly
+                .is_some_and(|monthly| spend.monthly_used_at(timestamp) >= monthly)
+    }
+
+    pub fn get_statuses(&self, timestamp: u64) -> Vec<BudgetStatus> {
+        let mut statuses: Vec<BudgetStatus> = self
+            .rpc_to_budget
+            .iter()
+            .map(|(rpc, budget)| {
+                let spend = self.rpc_to_spend.get(rpc).cloned().unwrap_or_default();
+                BudgetStatus {
+                    rpc: rpc.clone(),
+                    unit: budget.unit,
+                    daily_limit: budget.daily,
+                    daily_remaining: budget
+                        .daily
+                        .map(|daily| daily.saturating_sub(spend.daily_used_at(timestamp))),
+                    monthly_limit: budget.monthly,
+                    monthly_remaining: budget
+                        .monthly
+                        .map(|monthly| monthly.saturating_sub(spend.monthly_used_at(timestamp))),
+                    exhausted: self.is_exhausted(rpc, timestamp),
+                }
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.rpc.cmp(&b.rpc));
+        statuses
+    }
+
+    pub fn get_spends(&self) -> HashMap<String, BudgetSpend> {
+        self.rpc_to_spend.clone()
+    }
+
+    // spends restored from the snapshot are dropped by the next budgets without the rpc
+    pub fn restore_spends(&mut self, rpc_to_spend: HashMap<String, BudgetSpend>) {
+        self.rpc_to_spend.extend(rpc_to_spend);
+    }
+}
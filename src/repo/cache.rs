@@ -1,34 +1,110 @@
-use moka::sync::Cache;
+use std::collections::HashMap;
 
-use crate::{models::monitoring::Monitoring, services::evm_rpc::RpcMetrics};
+use crate::{
+    models::{
+        monitoring::Monitoring,
+        rule::BannedUpstream,
+        upstream::{Transport, Upstream},
+    },
+    services::evm_rpc::RpcMetrics,
+};
 
+// rankings are unbounded, every synced chain has to stay routable
 pub struct CacheRepo {
-    chain_id_to_rpcs_cache: Cache<String, Vec<(String, RpcMetrics)>>,
+    chain_id_to_rpcs: HashMap<(String, Transport), Vec<(Upstream, RpcMetrics)>>,
+    chain_id_to_banned: HashMap<String, Vec<BannedUpstream>>,
     monitoring: Monitoring,
 }
 
 impl CacheRepo {
     pub fn new() -> Self {
         Self {
-            chain_id_to_rpcs_cache: Cache::builder().max_capacity(1024).build(),
+            chain_id_to_rpcs: HashMap::new(),
+            chain_id_to_banned: HashMap::new(),
             monitoring: Monitoring::new(),
         }
     }
 
-    pub fn get_rpcs_for_chain_id(&self, chain_id: &str) -> Option<Vec<(String, RpcMetrics)>> {
-        self.chain_id_to_rpcs_cache.get(chain_id)
+    pub fn get_rpcs_for_chain_id(
+        &self,
+        chain_id: &str,
+        transport: Transport,
+    ) -> Option<Vec<(Upstream, RpcMetrics)>> {
+        self.chain_id_to_rpcs
+            .get(&(chain_id.to_string(), transport))
+            .cloned()
     }
 
-    pub fn set_rpcs_for_chain_id(&mut self, chain_id: &str, rpcs: Vec<(String, RpcMetrics)>) {
-        self.chain_id_to_rpcs_cache
-            .insert(chain_id.to_string(), rpcs);
+    pub fn set_rpcs_for_chain_id(
+        &mut self,
+        chain_id: &str,
+        transport: Transport,
+        rpcs: Vec<(Upstream, RpcMetrics)>,
+    ) {
+        // rankings of transports and chains which lost every target are pruned
+        if rpcs.is_empty() {
+            self.chain_id_to_rpcs
+                .remove(&(chain_id.to_string(), transport));
+        } else {
+            self.chain_id_to_rpcs
+                .insert((chain_id.to_string(), transport), rpcs);
+        }
+    }
+
+    pub fn get_banned_for_chain_id(&self, chain_id: &str) -> Vec<BannedUpstream> {
+        self.chain_id_to_banned
+            .get(chain_id)
+            .cloned()
+            .unwrap_or_default()
     }
 
-    pub fn get_monitoring(&self) -> Monitoring {
-        self.monitoring
+    pub fn set_banned_for_chain_id(&mut self, chain_id: &str, banned: Vec<BannedUpstream>) {
+        self.chain_id_to_banned.insert(chain_id.to_owned(), banned);
+    }
+
+    pub fn get_monitoring(&self) -> &Monitoring {
+        &self.monitoring
     }
 
     pub fn get_monitoring_mut(&mut self) -> &mut Monitoring {
         &mut self.monitoring
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_rankings_of_every_chain() {
+        let mut cache_repo = CacheRepo::new();
+        for chain_id in 0..2048 {
+            for transport in Transport::ALL {
+                let url = match transport {
+                    Transport::Http => format!("https://{chain_id}.example"),
+                    Transport::Ws => format!("wss://{chain_id}.example"),
+                };
+                cache_repo.set_rpcs_for_chain_id(
+                    &chain_id.to_string(),
+                    transport,
+                    vec![(Upstream::new(&url, "chainlist"), RpcMetrics::unprobed())],
+                );
+            }
+        }
+
+        for chain_id in [0, 1024, 2047] {
+            let rpcs = cache_repo
+                .get_rpcs_for_chain_id(&chain_id.to_string(), Transport::Http)
+                .unwrap();
+            assert_eq!(rpcs[0].0.url, format!("https://{chain_id}.example"));
+        }
+
+        cache_repo.set_rpcs_for_chain_id("0", Transport::Http, Vec::new());
+        assert!(cache_repo
+            .get_rpcs_for_chain_id("0", Transport::Http)
+            .is_none());
+        assert!(cache_repo
+            .get_rpcs_for_chain_id("0", Transport::Ws)
+            .is_some());
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use rocket::tokio::fs;
+
+use crate::models::chain::{ChainMetadata, ChainOverride};
+
+pub struct ChainRepo {
+    overrides_path: Option<PathBuf>,
+    // as fetched from the registry, before overrides
+    fetched_chains: HashMap<String, ChainMetadata>,
+    chain_id_to_metadata: HashMap<String, ChainMetadata>,
+    discovered_chain_ids: Vec<String>,
+}
+
+impl ChainRepo {
+    pub fn new(overrides_path: Option<PathBuf>) -> Self {
+        Self {
+            overrides_path,
+            fetched_chains: HashMap::new(),
+            chain_id_to_metadata: HashMap::new(),
+            discovered_chain_ids: Vec::new(),
+        }
+    }
+
+    pub fn get_overrides_path(&self) -> Option<PathBuf> {
+        self.overrides_path.clone()
+    }
+
+    // read without holding the repo lock
+    pub async fn load_overrides(path: Option<&Path>) -> Result<HashMap<String, ChainOverride>> {
+        let Some(path) = path else {
+            return Ok(HashMap::new());
+        };
+
+        let content = fs::read_to_string(path)
+            .await
+            .context(format!("failed to read {}", path.display()))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content).context("failed to parse toml"),
+            _ => serde_json::from_str(&content).context("failed to parse json"),
+        }
+    }
+
+    pub fn get_fetched_chains(&self) -> HashMap<String, ChainMetadata> {
+        self.fetched_chains.clone()
+    }
+
+    pub fn set_fetched_chains(&mut self, fetched_chains: HashMap<String, ChainMetadata>) {
+        self.fetched_chains = fetched_chains;
+    }
+
+    pub fn get_chain(&self, chain_id: &str) -> Option<ChainMetadata> {
+        self.chain_id_to_metadata.get(chain_id).cloned()
+    }
+
+    pub fn set_chains(&mut self, chain_id_to_metadata: HashMap<String, ChainMetadata>) {
+        self.chain_id_to_metadata = chain_id_to_metadata;
+    }
+
+    pub fn get_discovered_chain_ids(&self) -> &[String] {
+        &self.discovered_chain_ids
+    }
+
+    pub fn set_discovered_chain_ids(&mut self, discovered_chain_ids: Vec<String>) {
+        self.discovered_chain_ids = discovered_chain_ids;
+    }
+}
@@ -1,39 +1,387 @@
-use std::time::Duration;
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+
+use crate::models::{
+    egress::{EgressPolicy, GeoPolicy},
+    proxy::{ProxyCheckMode, ProxyConfig, ProxyProtocol, ProxySelection, ProxysellerOrder},
+    rule::Pattern,
+    upstream::PrivacyPolicy,
+};
+
+const CHAIN_PRIVACY_POLICY_PREFIX: &str = "PRIVACY_POLICY_";
+const CHAIN_EGRESS_POLICY_PREFIX: &str = "EGRESS_POLICY_";
+const CHAIN_EGRESS_COUNTRIES_PREFIX: &str = "EGRESS_COUNTRIES_";
+const DEFAULT_PROXY_CHECK_URL: &str = "https://ethereum-rpc.publicnode.com";
+const DEFAULT_CHAIN_REGISTRY_URL: &str = "https://chainid.network/chains.json";
+const DEFAULT_CHAINLIST_URL: &str =
+    "https://raw.githubusercontent.com/DefiLlama/chainlist/main/constants/extraRpcs.js";
 
 #[derive(Debug, Clone)]
 pub struct ConfigRepo {
     pub port: i32,
-    pub proxyseller_api_key: String,
+    pub proxy_provider: String,
+    pub proxy_list: Vec<ProxyConfig>,
+    pub proxy_file: Option<PathBuf>,
+    pub proxyseller_api_key: Option<String>,
+    pub proxyseller_protocol: ProxyProtocol,
+    pub proxyseller_orders: Vec<ProxysellerOrder>,
+    pub proxyseller_request_timeout: Duration,
+    pub proxyseller_check_timeout: Duration,
+    pub proxy_selection: ProxySelection,
+    pub proxy_max_failures: u32,
+    pub proxy_failover_attempts: usize,
+    pub proxy_direct_fallback: bool,
+    pub proxy_rotate_errors: u32,
+    pub proxy_daily_bytes: Option<u64>,
+    pub proxy_check: ProxyCheckMode,
+    pub proxy_check_url: String,
+    pub proxy_check_timeout: Duration,
     pub supported_chain_ids: Vec<String>,
+    pub all_chains: bool,
+    pub chain_include: Vec<Pattern>,
+    pub chain_exclude: Vec<Pattern>,
+    pub lazy_probe_interval: Duration,
     pub feed_max_timeout: Duration,
+    pub feed_request_tries: u32,
+    pub feed_success_threshold: f32,
+    pub probe_interval: Duration,
+    pub probe_top_interval: Duration,
+    pub probe_max_backoff: Duration,
+    pub probe_top_ranked: usize,
+    pub probe_concurrency: usize,
+    pub probe_chain_concurrency: usize,
+    pub traffic_ewma_alpha: f32,
+    pub traffic_weight: f32,
+    pub fork_check_depth: u64,
+    pub fork_quarantine: Duration,
+    pub rpc_sources: Vec<String>,
+    pub rpc_source_file: Option<PathBuf>,
+    pub chainlist_urls: Vec<String>,
+    pub upstream_rules_file: Option<PathBuf>,
+    pub chain_registry_url: String,
+    pub chain_overrides_file: Option<PathBuf>,
+    pub rpc_source_registry_url: Option<String>,
+    pub privacy_policy: PrivacyPolicy,
+    pub chain_privacy_policies: HashMap<String, PrivacyPolicy>,
+    pub egress_policy: EgressPolicy,
+    pub chain_egress_policies: HashMap<String, EgressPolicy>,
+    pub egress_latency_ratio: f32,
+    pub geo_policy: GeoPolicy,
+    pub chain_geo_policies: HashMap<String, GeoPolicy>,
+    pub snapshot_path: PathBuf,
 }
 
 fn get_env(name: &str) -> Result<String> {
     std::env::var(name).context(format!("failed to access \"{name}\" var"))
 }
 
+fn get_env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_owned())
+}
+
+fn parse_patterns(value: &str) -> Result<Vec<Pattern>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| Pattern::try_from(v.to_owned()).context(format!("invalid pattern {v}")))
+        .collect()
+}
+
 impl ConfigRepo {
     pub fn new() -> Result<Self> {
         let port = get_env("PORT")?
             .parse::<i32>()
             .context("failed to parse port")?;
-        let proxyseller_api_key = get_env("PROXYSELLER_API_KEY")?;
-        let supported_chain_ids: Vec<String> = get_env("SUPPORTED_CHAIN_IDS")?
+        let proxyseller_api_key = get_env("PROXYSELLER_API_KEY").ok();
+        // proxyseller stays the default for deployments which only have the api key
+        let proxy_provider =
+            get_env("PROXY_PROVIDER").unwrap_or_else(|_| match proxyseller_api_key {
+                Some(_) => String::from("proxyseller"),
+                None => String::from("none"),
+            });
+        let proxy_list: Vec<ProxyConfig> = get_env_or("PROXY_LIST", "")
             .split(',')
-            .map(|v| v.to_owned())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse::<ProxyConfig>())
+            .collect::<Result<Vec<ProxyConfig>>>()
+            .context("failed to parse proxy list")?;
+        let proxy_file = get_env("PROXY_FILE").ok().map(PathBuf::from);
+        let proxyseller_protocol = get_env_or("PROXYSELLER_PROTOCOL", "http")
+            .parse::<ProxyProtocol>()
+            .context("failed to parse proxyseller protocol")?;
+        let proxyseller_orders: Vec<ProxysellerOrder> = get_env_or("PROXYSELLER_ORDERS", "")
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse::<ProxysellerOrder>())
+            .collect::<Result<Vec<ProxysellerOrder>>>()
+            .context("failed to parse proxyseller orders")?;
+        let proxyseller_request_timeout = get_env_or("PROXYSELLER_REQUEST_TIMEOUT_MS", "10000")
+            .parse::<u64>()
+            .context("failed to parse proxyseller request timeout")
+            .map(Duration::from_millis)?;
+        let proxyseller_check_timeout = get_env_or("PROXYSELLER_CHECK_TIMEOUT_MS", "3000")
+            .parse::<u64>()
+            .context("failed to parse proxyseller check timeout")
+            .map(Duration::from_millis)?;
+        let proxy_selection = get_env_or("PROXY_SELECTION", "round_robin")
+            .parse::<ProxySelection>()
+            .context("failed to parse proxy selection")?;
+        let proxy_max_failures = get_env_or("PROXY_MAX_FAILURES", "3")
+            .parse::<u32>()
+            .context("failed to parse proxy max failures")?;
+        if proxy_max_failures == 0 {
+            bail!("proxy max failures should be greater than zero");
+        }
+        let proxy_failover_attempts = get_env_or("PROXY_FAILOVER_ATTEMPTS", "2")
+            .parse::<usize>()
+            .context("failed to parse proxy failover attempts")?;
+        // requests failed on every tried proxy go directly, exposing the own ip
+        let proxy_direct_fallback = get_env_or("PROXY_DIRECT_FALLBACK", "false")
+            .parse::<bool>()
+            .context("failed to parse proxy direct fallback")?;
+        // proxy errors per minute which make the pool to be fetched and checked again
+        let proxy_rotate_errors = get_env_or("PROXY_ROTATE_ERRORS", "20")
+            .parse::<u32>()
+            .context("failed to parse proxy rotate errors")?;
+        // daily byte budget of every proxy, proxies may set their own one
+        let proxy_daily_bytes = get_env("PROXY_DAILY_BYTES")
+            .ok()
+            .map(|v| v.parse::<u64>())
+            .transpose()
+            .context("failed to parse proxy daily bytes")?;
+        let proxy_check = get_env_or("PROXY_CHECK", "builtin")
+            .parse::<ProxyCheckMode>()
+            .context("failed to parse proxy check")?;
+        // reference upstream or a local endpoint answering json rpc
+        let proxy_check_url = get_env_or("PROXY_CHECK_URL", DEFAULT_PROXY_CHECK_URL);
+        let proxy_check_timeout = get_env_or("PROXY_CHECK_TIMEOUT_MS", "5000")
+            .parse::<u64>()
+            .context("failed to parse proxy check timeout")
+            .map(Duration::from_millis)?;
+        // in all chains mode chains found by rpc sources are served as well,
+        // supported chains are the ones which are always probed
+        let all_chains = get_env_or("ALL_CHAINS", "false")
+            .parse::<bool>()
+            .context("failed to parse all chains")?;
+        let supported_chain_ids = if all_chains {
+            get_env_or("SUPPORTED_CHAIN_IDS", "")
+        } else {
+            get_env("SUPPORTED_CHAIN_IDS")?
+        };
+        let supported_chain_ids: Vec<String> = supported_chain_ids
+            .split(',')
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
             .collect();
+        let chain_include = parse_patterns(&get_env_or("CHAIN_INCLUDE", ""))
+            .context("failed to parse chain include")?;
+        let chain_exclude = parse_patterns(&get_env_or("CHAIN_EXCLUDE", ""))
+            .context("failed to parse chain exclude")?;
+        let lazy_probe_interval = get_env_or("LAZY_PROBE_INTERVAL_S", "3600")
+            .parse::<u64>()
+            .context("failed to parse lazy probe interval")
+            .map(Duration::from_secs)?;
         let feed_max_timeout = get_env("FEED_MAX_TIMEOUT_MS")?
             .parse::<u32>()
             .context("failed to parse feed max timeout")
             .map(|val| Duration::new(0, val * 1_000_000))?;
+        let feed_request_tries = get_env_or("FEED_REQUEST_TRIES", "3")
+            .parse::<u32>()
+            .context("failed to parse feed request tries")?;
+        if feed_request_tries == 0 {
+            bail!("feed request tries should be greater than zero");
+        }
+        let feed_success_threshold = get_env_or("FEED_SUCCESS_THRESHOLD", "0.5")
+            .parse::<f32>()
+            .context("failed to parse feed success threshold")?;
+        if !(0.0..=1.0).contains(&feed_success_threshold) {
+            bail!("feed success threshold should be between 0 and 1");
+        }
+        let probe_interval = get_env_or("PROBE_INTERVAL_S", "300")
+            .parse::<u64>()
+            .context("failed to parse probe interval")
+            .map(Duration::from_secs)?;
+        let probe_top_interval = get_env_or("PROBE_TOP_INTERVAL_S", "60")
+            .parse::<u64>()
+            .context("failed to parse probe top interval")
+            .map(Duration::from_secs)?;
+        let probe_max_backoff = get_env_or("PROBE_MAX_BACKOFF_S", "3600")
+            .parse::<u64>()
+            .context("failed to parse probe max backoff")
+            .map(Duration::from_secs)?;
+        let probe_top_ranked = get_env_or("PROBE_TOP_RANKED", "3")
+            .parse::<usize>()
+            .context("failed to parse probe top ranked")?;
+        let probe_concurrency = get_env_or("PROBE_CONCURRENCY", "20")
+            .parse::<usize>()
+            .context("failed to parse probe concurrency")?;
+        let probe_chain_concurrency = get_env_or("PROBE_CHAIN_CONCURRENCY", "5")
+            .parse::<usize>()
+            .context("failed to parse probe chain concurrency")?;
+        if probe_concurrency == 0 || probe_chain_concurrency == 0 {
+            bail!("probe concurrency should be greater than zero");
+        }
+        let traffic_ewma_alpha = get_env_or("TRAFFIC_EWMA_ALPHA", "0.2")
+            .parse::<f32>()
+            .context("failed to parse traffic ewma alpha")?;
+        if !(0.0..=1.0).contains(&traffic_ewma_alpha) || traffic_ewma_alpha == 0.0 {
+            bail!("traffic ewma alpha should be in (0, 1] range");
+        }
+        let traffic_weight = get_env_or("TRAFFIC_WEIGHT", "0.7")
+            .parse::<f32>()
+            .context("failed to parse traffic weight")?;
+        if !(0.0..=1.0).contains(&traffic_weight) {
+            bail!("traffic weight should be between 0 and 1");
+        }
+        let fork_check_depth = get_env_or("FORK_CHECK_DEPTH", "5")
+            .parse::<u64>()
+            .context("failed to parse fork check depth")?;
+        let fork_quarantine = get_env_or("FORK_QUARANTINE_S", "900")
+            .parse::<u64>()
+            .context("failed to parse fork quarantine")
+            .map(Duration::from_secs)?;
+        let rpc_sources: Vec<String> = get_env_or("RPC_SOURCES", "chainlist")
+            .split(',')
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
+            .collect();
+        let rpc_source_file = get_env("RPC_SOURCE_FILE").ok().map(PathBuf::from);
+        let rpc_source_registry_url = get_env("RPC_SOURCE_REGISTRY_URL").ok();
+        // mirrors in order of preference, `file://` urls are read from disk
+        let chainlist_urls: Vec<String> = get_env_or("CHAINLIST_URLS", DEFAULT_CHAINLIST_URL)
+            .split(',')
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
+            .collect();
+        if chainlist_urls.is_empty() {
+            bail!("chainlist urls should not be empty");
+        }
+        let upstream_rules_file = get_env("UPSTREAM_RULES_FILE").ok().map(PathBuf::from);
+        let chain_registry_url = get_env_or("CHAIN_REGISTRY_URL", DEFAULT_CHAIN_REGISTRY_URL);
+        let chain_overrides_file = get_env("CHAIN_OVERRIDES_FILE").ok().map(PathBuf::from);
+        let privacy_policy = get_env_or("PRIVACY_POLICY", "any")
+            .parse::<PrivacyPolicy>()
+            .context("failed to parse privacy policy")?;
+        let mut chain_privacy_policies: HashMap<String, PrivacyPolicy> = HashMap::new();
+        for (name, value) in std::env::vars() {
+            let Some(chain_id) = name.strip_prefix(CHAIN_PRIVACY_POLICY_PREFIX) else {
+                continue;
+            };
+            let chain_privacy_policy = value
+                .parse::<PrivacyPolicy>()
+                .context(format!("failed to parse \"{name}\" var"))?;
+            chain_privacy_policies.insert(chain_id.to_owned(), chain_privacy_policy);
+        }
+        let egress_policy = get_env_or("EGRESS_POLICY", "auto")
+            .parse::<EgressPolicy>()
+            .context("failed to parse egress policy")?;
+        let mut chain_egress_policies: HashMap<String, EgressPolicy> = HashMap::new();
+        for (name, value) in std::env::vars() {
+            let Some(chain_id) = name.strip_prefix(CHAIN_EGRESS_POLICY_PREFIX) else {
+                continue;
+            };
+            let chain_egress_policy = value
+                .parse::<EgressPolicy>()
+                .context(format!("failed to parse \"{name}\" var"))?;
+            chain_egress_policies.insert(chain_id.to_owned(), chain_egress_policy);
+        }
+        let egress_latency_ratio = get_env_or("EGRESS_LATENCY_RATIO", "2.0")
+            .parse::<f32>()
+            .context("failed to parse egress latency ratio")?;
+        if egress_latency_ratio < 1.0 {
+            bail!("egress latency ratio should be at least 1");
+        }
+        let geo_policy = get_env_or("EGRESS_COUNTRIES", "")
+            .parse::<GeoPolicy>()
+            .context("failed to parse egress countries")?;
+        let mut chain_geo_policies: HashMap<String, GeoPolicy> = HashMap::new();
+        for (name, value) in std::env::vars() {
+            let Some(chain_id) = name.strip_prefix(CHAIN_EGRESS_COUNTRIES_PREFIX) else {
+                continue;
+            };
+            let chain_geo_policy = value
+                .parse::<GeoPolicy>()
+                .context(format!("failed to parse \"{name}\" var"))?;
+            chain_geo_policies.insert(chain_id.to_owned(), chain_geo_policy);
+        }
+        let snapshot_path = PathBuf::from(get_env_or("SNAPSHOT_PATH", "snapshot.json"));
 
         Ok(Self {
             port,
+            proxy_provider,
+            proxy_list,
+            proxy_file,
             proxyseller_api_key,
+            proxyseller_protocol,
+            proxyseller_orders,
+            proxyseller_request_timeout,
+            proxyseller_check_timeout,
+            proxy_selection,
+            proxy_max_failures,
+            proxy_failover_attempts,
+            proxy_direct_fallback,
+            proxy_rotate_errors,
+            proxy_daily_bytes,
+            proxy_check,
+            proxy_check_url,
+            proxy_check_timeout,
             supported_chain_ids,
+            all_chains,
+            chain_include,
+            chain_exclude,
+            lazy_probe_interval,
             feed_max_timeout,
+            feed_request_tries,
+            feed_success_threshold,
+            probe_interval,
+            probe_top_interval,
+            probe_max_backoff,
+            probe_top_ranked,
+            probe_concurrency,
+            probe_chain_concurrency,
+            traffic_ewma_alpha,
+            traffic_weight,
+            fork_check_depth,
+            fork_quarantine,
+            rpc_sources,
+            rpc_source_file,
+            chainlist_urls,
+            upstream_rules_file,
+            chain_registry_url,
+            chain_overrides_file,
+            rpc_source_registry_url,
+            privacy_policy,
+            chain_privacy_policies,
+            egress_policy,
+            chain_egress_policies,
+            egress_latency_ratio,
+            geo_policy,
+            chain_geo_policies,
+            snapshot_path,
         })
     }
+
+    pub fn privacy_policy_for(&self, chain_id: &str) -> PrivacyPolicy {
+        self.chain_privacy_policies
+            .get(chain_id)
+            .copied()
+            .unwrap_or(self.privacy_policy)
+    }
+
+    pub fn geo_policy_for(&self, chain_id: &str) -> &GeoPolicy {
+        self.chain_geo_policies
+            .get(chain_id)
+            .unwrap_or(&self.geo_policy)
+    }
+
+    pub fn egress_policy_for(&self, chain_id: &str) -> EgressPolicy {
+        self.chain_egress_policies
+            .get(chain_id)
+            .copied()
+            .unwrap_or(self.egress_policy)
+    }
 }
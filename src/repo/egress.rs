@@ -0,0 +1,99 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::models::egress::Egress;
+
+// consecutive samples which have to agree before the egress of an upstream is changed
+const EGRESS_SAMPLES: u32 = 3;
+
+struct EgressState {
+    egress: Option<Egress>,
+    candidate: Option<Egress>,
+    samples: u32,
+}
+
+pub struct EgressRepo {
+    rpc_to_egress: HashMap<String, EgressState>,
+}
+
+impl EgressRepo {
+    pub fn new() -> Self {
+        Self {
+            rpc_to_egress: HashMap::new(),
+        }
+    }
+
+    // keeps the previous choice when both egresses failed or until the other egress wins
+    // enough samples in a row, returns the changed choice
+    pub fn record(
+        &mut self,
+        rpc: &str,
+        direct: Option<Duration>,
+        proxied: Option<Duration>,
+        latency_ratio: f32,
+    ) -> Option<Egress> {
+        let egress = Egress::choose(direct, proxied, latency_ratio)?;
+        let state = self
+            .rpc_to_egress
+            .entry(rpc.to_owned())
+            .or_insert(EgressState {
+                egress: None,
+                candidate: None,
+                samples: 0,
+            });
+        if state.egress == Some(egress) {
+            state.candidate = None;
+            state.samples = 0;
+            return None;
+        }
+
+        if state.candidate == Some(egress) {
+            state.samples += 1;
+        } else {
+            state.candidate = Some(egress);
+            state.samples = 1;
+        }
+        if state.samples < EGRESS_SAMPLES {
+            return None;
+        }
+
+        state.egress = Some(egress);
+        state.candidate = None;
+        state.samples = 0;
+        Some(egress)
+    }
+
+    pub fn get_egress(&self, rpc: &str) -> Option<Egress> {
+        self.rpc_to_egress.get(rpc).and_then(|state| state.egress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAST: Option<Duration> = Some(Duration::from_millis(100));
+    const SLOW: Option<Duration> = Some(Duration::from_millis(1000));
+
+    #[test]
+    fn switches_after_agreeing_samples() {
+        let mut egress_repo = EgressRepo::new();
+        let rpc = "https://rpc.example";
+
+        assert_eq!(egress_repo.record(rpc, FAST, SLOW, 2.0), None);
+        assert_eq!(egress_repo.record(rpc, FAST, SLOW, 2.0), None);
+        assert_eq!(egress_repo.get_egress(rpc), None);
+        assert_eq!(
+            egress_repo.record(rpc, FAST, SLOW, 2.0),
+            Some(Egress::Direct)
+        );
+        assert_eq!(egress_repo.get_egress(rpc), Some(Egress::Direct));
+
+        // a single proxied sample between direct ones does not switch
+        assert_eq!(egress_repo.record(rpc, FAST, FAST, 2.0), None);
+        assert_eq!(egress_repo.record(rpc, FAST, FAST, 2.0), None);
+        assert_eq!(egress_repo.record(rpc, FAST, SLOW, 2.0), None);
+        assert_eq!(egress_repo.record(rpc, FAST, FAST, 2.0), None);
+        assert_eq!(egress_repo.record(rpc, None, None, 2.0), None);
+        assert_eq!(egress_repo.get_egress(rpc), Some(Egress::Direct));
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::{models::history::HistoryPoint, util::stable_id};
+
+struct HistoryTier {
+    resolution_s: u64,
+    capacity: usize,
+}
+
+// every tier keeps buckets of the given resolution, the oldest buckets of a full tier
+// are merged into the next (coarser) one and dropped from the last tier
+const HISTORY_TIERS: [HistoryTier; 3] = [
+    // 6 hours by minute
+    HistoryTier {
+        resolution_s: 60,
+        capacity: 360,
+    },
+    // 3 days by 15 minutes
+    HistoryTier {
+        resolution_s: 900,
+        capacity: 288,
+    },
+    // 60 days by 4 hours
+    HistoryTier {
+        resolution_s: 14_400,
+        capacity: 360,
+    },
+];
+
+#[derive(Clone, Copy, Default)]
+struct HistoryBucket {
+    start: u64,
+    checks: u64,
+    failed_checks: u64,
+    check_time_ms: u128,
+    reliability: f64,
+    requests: u64,
+    failed_requests: u64,
+    request_time_ms: u128,
+}
+
+impl HistoryBucket {
+    fn merge(&mut self, other: &HistoryBucket) {
+        self.checks += other.checks;
+        self.failed_checks += other.failed_checks;
+        self.check_time_ms += other.check_time_ms;
+        self.reliability += other.reliability;
+        self.requests += other.requests;
+        self.failed_requests += other.failed_requests;
+        self.request_time_ms += other.request_time_ms;
+    }
+
+    fn to_point(self, resolution_s: u64) -> HistoryPoint {
+        let successful_checks = self.checks - self.failed_checks;
+        let successful_requests = self.requests - self.failed_requests;
+        HistoryPoint {
+            timestamp: self.start,
+            resolution_s,
+            checks: self.checks,
+            failed_checks: self.failed_checks,
+            avg_response_time_ms: average(self.check_time_ms, successful_checks),
+            avg_reliability: match self.checks {
+                0 => None,
+                checks => Some((self.reliability / checks as f64) as f32),
+            },
+            requests: self.requests,
+            failed_requests: self.failed_requests,
+            avg_request_time_ms: average(self.request_time_ms, successful_requests),
+        }
+    }
+}
+
+fn average(total: u128, count: u64) -> Option<u128> {
+    match count {
+        0 => None,
+        count => Some(total / count as u128),
+    }
+}
+
+pub struct RpcHistory {
+    rpc: String,
+    tiers: [VecDeque<HistoryBucket>; HISTORY_TIERS.len()],
+}
+
+impl RpcHistory {
+    fn new(rpc: &str) -> Self {
+        Self {
+            rpc: rpc.to_owned(),
+            tiers: Default::default(),
+        }
+    }
+
+    fn record(&mut self, sample: HistoryBucket) {
+        push_bucket(&mut self.tiers[0], HISTORY_TIERS[0].resolution_s, sample);
+
+        for (i, tier) in HISTORY_TIERS.iter().enumerate() {
+            while self.tiers[i].len() > tier.capacity {
+                let Some(bucket) = self.tiers[i].pop_front() else {
+                    break;
+                };
+                if let Some(next_tier) = HISTORY_TIERS.get(i + 1) {
+                    push_bucket(&mut self.tiers[i + 1], next_tier.resolution_s, bucket);
+                }
+            }
+        }
+    }
+
+    pub fn record_check(&mut self, timestamp: u64, check: Option<(u128, f32)>) {
+        let (check_time_ms, reliability) = check.unwrap_or_default();
+        self.record(HistoryBucket {
+            start: timestamp,
+            checks: 1,
+            failed_checks: u64::from(check.is_none()),
+            check_time_ms,
+            reliability: reliability as f64,
+            ..Default::default()
+        });
+    }
+
+    pub fn record_request(&mut self, timestamp: u64, request_time_ms: u128, success: bool) {
+        self.record(HistoryBucket {
+            start: timestamp,
+            requests: 1,
+            failed_requests: u64::from(!success),
+            request_time_ms: if success { request_time_ms } else { 0 },
+            ..Default::default()
+        });
+    }
+
+    fn query(&self, from: u64, to: u64) -> Vec<HistoryPoint> {
+        let mut points: Vec<HistoryPoint> = Vec::new();
+        for (tier, buckets) in HISTORY_TIERS.iter().zip(self.tiers.iter()).rev() {
+            points.extend(
+                buckets
+                    .iter()
+                    .filter(|bucket| bucket.start + tier.resolution_s > from && bucket.start <= to)
+                    .map(|bucket| bucket.to_point(tier.resolution_s)),
+            );
+        }
+        points
+    }
+}
+
+fn push_bucket(buckets: &mut VecDeque<HistoryBucket>, resolution_s: u64, sample: HistoryBucket) {
+    let start = sample.start - sample.start % resolution_s;
+    match buckets.back_mut() {
+        Some(last) if last.start == start => last.merge(&sample),
+        _ => buckets.push_back(HistoryBucket { start, ..sample }),
+    }
+}
+
+// histories are created once per upstream and then recorded under a read lock
+pub struct HistoryRepo {
+    chain_id_to_histories: HashMap<String, HashMap<String, Arc<Mutex<RpcHistory>>>>,
+}
+
+impl HistoryRepo {
+    pub fn new() -> Self {
+        Self {
+            chain_id_to_histories: HashMap::new(),
+        }
+    }
+
+    pub fn get_rpc_history(&self, chain_id: &str, rpc: &str) -> Option<Arc<Mutex<RpcHistory>>> {
+        self.chain_id_to_histories
+            .get(chain_id)
+            .and_then(|histories| histories.get(&stable_id(rpc)))
+            .cloned()
+    }
+
+    pub fn add_rpc_history(&mut self, chain_id: &str, rpc: &str) -> Arc<Mutex<RpcHistory>> {
+        self.chain_id_to_histories
+            .entry(chain_id.to_owned())
+            .or_default()
+            .entry(stable_id(rpc))
+            .or_insert_with(|| Arc::new(Mutex::new(RpcHistory::new(rpc))))
+            .clone()
+    }
+
+    // drops histories of upstreams which left the feed
+    pub fn retain(&mut self, chain_id_to_rpcs: &HashMap<String, HashSet<String>>) {
+        self.chain_id_to_histories.retain(|chain_id, histories| {
+            let Some(rpcs) = chain_id_to_rpcs.get(chain_id) else {
+                return false;
+            };
+            histories.retain(|rpc_id, _| rpcs.contains(rpc_id));
+            !histories.is_empty()
+        });
+    }
+
+    pub fn get_history(
+        &self,
+        chain_id: &str,
+        rpc_id: &str,
+        from: u64,
+        to: u64,
+    ) -> Option<(String, Vec<HistoryPoint>)> {
+        let history = self.chain_id_to_histories.get(chain_id)?.get(rpc_id)?;
+        let history = history.lock().unwrap_or_else(PoisonError::into_inner);
+        Some((history.rpc.clone(), history.query(from, to)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-01 00:00:00, aligned to every tier
+    const TIMESTAMP: u64 = 1_704_067_200;
+
+    #[test]
+    fn merges_samples_of_the_same_bucket() {
+        let mut history = RpcHistory::new("https://rpc.example");
+        history.record_check(TIMESTAMP, Some((100, 1.0)));
+        history.record_check(TIMESTAMP + 30, None);
+        history.record_request(TIMESTAMP + 59, 40, true);
+        history.record_check(TIMESTAMP + 60, Some((300, 0.5)));
+
+        let points = history.query(TIMESTAMP, TIMESTAMP + 60);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].timestamp, TIMESTAMP);
+        assert_eq!(points[0].checks, 2);
+        assert_eq!(points[0].failed_checks, 1);
+        assert_eq!(points[0].avg_response_time_ms, Some(100));
+        assert_eq!(points[0].avg_reliability, Some(0.5));
+        assert_eq!(points[0].requests, 1);
+        assert_eq!(points[0].avg_request_time_ms, Some(40));
+        assert_eq!(points[1].timestamp, TIMESTAMP + 60);
+        assert_eq!(points[1].avg_response_time_ms, Some(300));
+    }
+
+    #[test]
+    fn downsamples_overflowing_buckets() {
+        let mut history = RpcHistory::new("https://rpc.example");
+        let minutes = HISTORY_TIERS[0].capacity as u64 + 16;
+        for minute in 0..minutes {
+            history.record_request(TIMESTAMP + minute * 60, 10, minute % 2 == 0);
+        }
+
+        assert_eq!(history.tiers[0].len(), HISTORY_TIERS[0].capacity);
+        assert_eq!(history.tiers[0][0].start, TIMESTAMP + 16 * 60);
+        // 16 oldest minutes fall into two 15 minute buckets
+        assert_eq!(history.tiers[1].len(), 2);
+        assert_eq!(history.tiers[1][0].start, TIMESTAMP);
+        assert_eq!(history.tiers[1][0].requests, 15);
+        assert_eq!(history.tiers[1][0].failed_requests, 7);
+        assert_eq!(history.tiers[1][1].start, TIMESTAMP + 900);
+        assert_eq!(history.tiers[1][1].requests, 1);
+
+        // coarse points come first
+        let points = history.query(TIMESTAMP, TIMESTAMP + minutes * 60);
+        assert_eq!(points.len(), 2 + HISTORY_TIERS[0].capacity);
+        assert_eq!(points[0].resolution_s, 900);
+        assert_eq!(points[0].avg_request_time_ms, Some(10));
+        assert_eq!(points[2].resolution_s, 60);
+    }
+
+    #[test]
+    fn drops_histories_which_left_the_feed() {
+        let mut repo = HistoryRepo::new();
+        repo.add_rpc_history("1", "https://a.example");
+        repo.add_rpc_history("1", "https://b.example");
+        repo.add_rpc_history("10", "https://c.example");
+
+        let chain_id_to_rpcs = HashMap::from([(
+            "1".to_owned(),
+            HashSet::from([stable_id("https://a.example")]),
+        )]);
+        repo.retain(&chain_id_to_rpcs);
+
+        assert!(repo.get_rpc_history("1", "https://a.example").is_some());
+        assert!(repo.get_rpc_history("1", "https://b.example").is_none());
+        assert!(repo.get_rpc_history("10", "https://c.example").is_none());
+    }
+}
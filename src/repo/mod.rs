@@ -1,2 +1,10 @@
+pub mod bandwidth;
+pub mod budget;
 pub mod cache;
+pub mod chain;
 pub mod config;
+pub mod egress;
+pub mod history;
+pub mod rule;
+pub mod snapshot;
+pub mod traffic;
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use rocket::tokio::fs;
+
+use crate::models::rule::{UpstreamRule, UpstreamRules};
+
+pub struct RuleRepo {
+    path: Option<PathBuf>,
+}
+
+impl RuleRepo {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    // re-read on every feed run, so operators can edit bans without a restart
+    pub async fn load(&self) -> Result<Vec<UpstreamRule>> {
+        let Some(path) = &self.path else {
+            return Ok(Vec::new());
+        };
+
+        let content = fs::read_to_string(path)
+            .await
+            .context(format!("failed to read {}", path.display()))?;
+        let rules: UpstreamRules = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content).context("failed to parse toml")?,
+            _ => serde_json::from_str(&content).context("failed to parse json")?,
+        };
+
+        Ok(rules.rules)
+    }
+}
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use rocket::tokio::fs;
+
+use crate::models::snapshot::{Snapshot, SNAPSHOT_VERSION};
+
+pub struct SnapshotRepo {
+    path: PathBuf,
+}
+
+impl SnapshotRepo {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub async fn load(&self) -> Result<Option<Snapshot>> {
+        if !fs::try_exists(&self.path).await.unwrap_or_default() {
+            return Ok(None);
+        }
+
+        let content = fs::read(&self.path)
+            .await
+            .context(format!("failed to read {}", self.path.display()))?;
+        let snapshot: Snapshot =
+            serde_json::from_slice(&content).context("failed to parse snapshot")?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            bail!("unsupported snapshot version {}", snapshot.version);
+        }
+
+        Ok(Some(snapshot))
+    }
+
+    // written to a temporary file first, so a crash never leaves a truncated snapshot
+    pub async fn save(&self, snapshot: &Snapshot) -> Result<()> {
+        let content = serde_json::to_vec(snapshot).context("failed to serialize snapshot")?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, content)
+            .await
+            .context(format!("failed to write {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .await
+            .context(format!("failed to replace {}", self.path.display()))
+    }
+}
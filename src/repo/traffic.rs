@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use crate::models::traffic::{CountryTraffic, MethodClass, TrafficMetrics};
+
+pub struct TrafficRepo {
+    chain_id_to_traffic: HashMap<String, HashMap<String, Vec<TrafficMetrics>>>,
+    chain_id_to_country_traffic: HashMap<String, HashMap<String, Vec<CountryTraffic>>>,
+}
+
+impl TrafficRepo {
+    pub fn new() -> Self {
+        Self {
+            chain_id_to_traffic: HashMap::new(),
+            chain_id_to_country_traffic: HashMap::new(),
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        chain_id: &str,
+        rpc: &str,
+        method_class: MethodClass,
+        latency_ms: f32,
+        success: bool,
+        alpha: f32,
+    ) {
+        let rpc_traffic = self
+            .chain_id_to_traffic
+            .entry(chain_id.to_owned())
+            .or_default()
+            .entry(rpc.to_owned())
+            .or_default();
+
+        let position = rpc_traffic
+            .iter()
+            .position(|metrics| metrics.method_class == method_class);
+        let metrics = match position {
+            Some(position) => &mut rpc_traffic[position],
+            None => {
+                rpc_traffic.push(TrafficMetrics::new(method_class));
+                rpc_traffic.last_mut().expect("unreachable")
+            }
+        };
+
+        metrics.record(latency_ms, success, alpha);
+    }
+
+    pub fn get_traffic(
+        &self,
+        chain_id: &str,
+        rpc: &str,
+        method_class: MethodClass,
+    ) -> Option<&TrafficMetrics> {
+        self.chain_id_to_traffic
+            .get(chain_id)?
+            .get(rpc)?
+            .iter()
+            .find(|metrics| metrics.method_class == method_class)
+    }
+
+    pub fn get_traffic_for_chain_id(&self, chain_id: &str) -> HashMap<String, Vec<TrafficMetrics>> {
+        self.chain_id_to_traffic
+            .get(chain_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn record_country(
+        &mut self,
+        chain_id: &str,
+        rpc: &str,
+        country: &str,
+        latency_ms: f32,
+        success: bool,
+        alpha: f32,
+    ) {
+        let rpc_traffic = self
+            .chain_id_to_country_traffic
+            .entry(chain_id.to_owned())
+            .or_default()
+            .entry(rpc.to_owned())
+            .or_default();
+
+        let position = rpc_traffic
+            .iter()
+            .position(|traffic| traffic.country == country);
+        let traffic = match position {
+            Some(position) => &mut rpc_traffic[position],
+            None => {
+                rpc_traffic.push(CountryTraffic::new(country));
+                rpc_traffic.last_mut().expect("unreachable")
+            }
+        };
+
+        traffic.record(latency_ms, success, alpha);
+    }
+
+    pub fn get_country_traffic_for_chain_id(
+        &self,
+        chain_id: &str,
+    ) -> HashMap<String, Vec<CountryTraffic>> {
+        self.chain_id_to_country_traffic
+            .get(chain_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::HashMap, sync::Arc};
+
+use rocket::tokio::sync::RwLock;
+
+use crate::{
+    client::chain_registry::ChainRegistryClient,
+    models::chain::ChainMetadata,
+    repo::{chain::ChainRepo, config::ConfigRepo},
+};
+
+pub struct ChainService {
+    chain_repo: Arc<RwLock<ChainRepo>>,
+    chain_registry_client: ChainRegistryClient,
+    config_repo: ConfigRepo,
+}
+
+impl ChainService {
+    pub fn new(
+        chain_repo: Arc<RwLock<ChainRepo>>,
+        chain_registry_client: ChainRegistryClient,
+        config_repo: ConfigRepo,
+    ) -> Self {
+        Self {
+            chain_repo,
+            chain_registry_client,
+            config_repo,
+        }
+    }
+
+    pub async fn is_supported(&self, chain_id: &str) -> bool {
+        self.config_repo
+            .supported_chain_ids
+            .iter()
+            .any(|val| val == chain_id)
+            || self
+                .chain_repo
+                .read()
+                .await
+                .get_discovered_chain_ids()
+                .iter()
+                .any(|val| val == chain_id)
+    }
+
+    // supported chains first, then discovered ones
+    pub async fn get_chain_ids(&self) -> Vec<String> {
+        let mut chain_ids = self.config_repo.supported_chain_ids.clone();
+        chain_ids.extend_from_slice(self.chain_repo.read().await.get_discovered_chain_ids());
+        chain_ids
+    }
+
+    // picks chains found by rpc sources in all chains mode, supported chains are not repeated
+    pub async fn discover<'a>(&self, chain_ids: impl Iterator<Item = &'a String>) -> Vec<String> {
+        if !self.config_repo.all_chains {
+            return Vec::new();
+        }
+
+        let mut discovered_chain_ids: Vec<String> = chain_ids
+            .filter(|chain_id| !self.config_repo.supported_chain_ids.contains(chain_id))
+            .filter(|chain_id| {
+                self.config_repo.chain_include.is_empty()
+                    || self
+                        .config_repo
+                        .chain_include
+                        .iter()
+                        .any(|pattern| pattern.is_match(chain_id))
+            })
+            .filter(|chain_id| {
+                !self
+                    .config_repo
+                    .chain_exclude
+                    .iter()
+                    .any(|pattern| pattern.is_match(chain_id))
+            })
+            .cloned()
+            .collect();
+        discovered_chain_ids.sort();
+
+        self.chain_repo
+            .write()
+            .await
+            .set_discovered_chain_ids(discovered_chain_ids.clone());
+        discovered_chain_ids
+    }
+
+    // fetched chains are kept when the registry is unreachable, overrides are applied on top
+    pub async fn refresh(&self) {
+        match self.chain_registry_client.fetch_chains().await {
+            Ok(chains) => self.chain_repo.write().await.set_fetched_chains(
+                chains
+                    .into_iter()
+                    .map(|chain| (chain.chain_id.clone(), chain))
+                    .collect(),
+            ),
+            Err(err) => log::error!("failed to fetch chain registry: {err:#}"),
+        }
+
+        let overrides_path = self.chain_repo.read().await.get_overrides_path();
+        let overrides = ChainRepo::load_overrides(overrides_path.as_deref())
+            .await
+            .map_err(|err| log::error!("failed to load chain overrides: {err:#}"))
+            .unwrap_or_default();
+
+        let mut chain_repo = self.chain_repo.write().await;
+        let mut chain_id_to_metadata: HashMap<String, ChainMetadata> =
+            chain_repo.get_fetched_chains();
+        for (chain_id, chain_override) in overrides {
+            chain_id_to_metadata
+                .entry(chain_id.clone())
+                .or_insert_with(|| ChainMetadata::new(&chain_id))
+                .apply(&chain_override);
+        }
+        chain_repo.set_chains(chain_id_to_metadata);
+    }
+
+    // chains missing in the registry still have their id
+    pub async fn get_chain(&self, chain_id: &str) -> ChainMetadata {
+        self.chain_repo
+            .read()
+            .await
+            .get_chain(chain_id)
+            .unwrap_or_else(|| ChainMetadata::new(chain_id))
+    }
+}
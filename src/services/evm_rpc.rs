@@ -2,17 +2,32 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use anyhow::bail;
-use reqwest::Client;
-use rocket::tokio::sync::RwLock;
+use futures::{SinkExt, StreamExt};
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use rocket::tokio::{sync::RwLock, time};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use thiserror::Error;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
 
-use crate::client::chainlist::ChainlistClient;
+use crate::client::source::{fetch_from_sources, ChainToUpstreams, RpcSource};
+use crate::models::budget::{Budget, BudgetSpend};
+use crate::models::egress::Egress;
 use crate::models::proxy::ProxyConfig;
+use crate::models::rule::BannedUpstream;
+use crate::models::traffic::{CountryTraffic, MethodClass, TrafficMetrics};
+use crate::models::upstream::{Transport, Upstream};
+use crate::repo::budget::BudgetRepo;
 use crate::repo::cache::CacheRepo;
+use crate::repo::config::ConfigRepo;
+use crate::repo::egress::EgressRepo;
+use crate::repo::traffic::TrafficRepo;
+use crate::services::proxy::ProxyService;
+use crate::util::unix_timestamp;
+
+// number of live requests after which traffic metrics get the full weight in the ranking
+const TRAFFIC_WARMUP_REQUESTS: u64 = 20;
 
 #[derive(Debug, Error)]
 pub enum EvmRpcError {
@@ -26,6 +41,54 @@ pub enum EvmRpcError {
     Proxy(String),
     #[error("rpc timeout")]
     Timeout,
+    #[error("unhealthy: {0}")]
+    Unhealthy(String),
+}
+
+// messages of socks errors which are caused by the proxy itself,
+// the others are replies about the target
+const SOCKS_PROXY_ERRORS: [&str; 7] = [
+    "proxy server unreachable",
+    "invalid response version",
+    "no acceptable auth methods",
+    "unknown auth method",
+    "invalid auth values",
+    "password auth failure",
+    "authorization required",
+];
+
+// only failures to reach or to authenticate with the proxy are charged to the proxy,
+// refused tunnels, target connect errors, resets and timeouts stay charged to the upstream
+fn is_proxy_error(err: &reqwest::Error) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if is_proxy_failure(&err.to_string()) {
+            return true;
+        }
+        source = err.source();
+    }
+
+    false
+}
+
+fn is_proxy_failure(message: &str) -> bool {
+    let message = message.to_lowercase();
+    if let Some(socks_error) = message.strip_prefix("socks connect error: ") {
+        // io errors happen on the connection with the proxy
+        return socks_error.contains("(os error")
+            || SOCKS_PROXY_ERRORS
+                .iter()
+                .any(|proxy_error| socks_error.starts_with(proxy_error));
+    }
+
+    // the http connector dials the proxy for proxied requests
+    [
+        "tcp connect error",
+        "dns error",
+        "proxy authentication required",
+    ]
+    .iter()
+    .any(|proxy_error| message.starts_with(proxy_error))
 }
 
 // impl Display for EvmRpcError {
@@ -48,32 +111,65 @@ pub enum EvmRpcError {
 
 #[derive(Deserialize)]
 struct EvmRpcTestResponse {
-    jsonrpc: String,
-    id: u32,
     result: String,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RpcMetrics {
     pub response_time_ms: u128,
+    /// Share of successful probes in the last health check, from 0.0 to 1.0
+    pub reliability: f32,
+    /// Reasons of the probes which failed during the last health check
+    pub failed_probes: Vec<String>,
+    pub last_probed_at: Option<u64>,
 }
 
 impl RpcMetrics {
+    pub fn unprobed() -> Self {
+        Self {
+            response_time_ms: 0,
+            reliability: 1.0,
+            failed_probes: Vec::new(),
+            last_probed_at: None,
+        }
+    }
+
     pub fn to_score(&self) -> f32 {
-        1.0 / self.response_time_ms as f32
+        if self.last_probed_at.is_none() {
+            return 0.0;
+        }
+        self.reliability / self.response_time_ms.max(1) as f32
     }
 }
 
 pub struct EvmRpcService {
     cache_repo: Arc<RwLock<CacheRepo>>,
-    chainlist_client: Box<ChainlistClient>,
+    traffic_repo: Arc<RwLock<TrafficRepo>>,
+    budget_repo: Arc<RwLock<BudgetRepo>>,
+    egress_repo: Arc<RwLock<EgressRepo>>,
+    proxy_service: Arc<ProxyService>,
+    rpc_sources: Vec<Box<dyn RpcSource>>,
+    config_repo: ConfigRepo,
 }
 
 impl EvmRpcService {
-    pub fn new(cache_repo: Arc<RwLock<CacheRepo>>, chainlist_client: Box<ChainlistClient>) -> Self {
+    pub fn new(
+        cache_repo: Arc<RwLock<CacheRepo>>,
+        traffic_repo: Arc<RwLock<TrafficRepo>>,
+        budget_repo: Arc<RwLock<BudgetRepo>>,
+        egress_repo: Arc<RwLock<EgressRepo>>,
+        proxy_service: Arc<ProxyService>,
+        rpc_sources: Vec<Box<dyn RpcSource>>,
+        config_repo: ConfigRepo,
+    ) -> Self {
         Self {
             cache_repo,
-            chainlist_client,
+            traffic_repo,
+            budget_repo,
+            egress_repo,
+            proxy_service,
+            rpc_sources,
+            config_repo,
         }
     }
 
@@ -101,46 +197,220 @@ impl EvmRpcService {
 
     pub async fn rpc_request(
         &self,
+        chain_id: &str,
         rpc: &str,
         proxy_config: Option<&ProxyConfig>,
         body: &Value,
         timeout: Duration,
     ) -> Result<Value, EvmRpcError> {
+        let request = serde_json::to_vec(body)
+            .map_err(|err| EvmRpcError::Internal(format!("serialize error: {err}")))?;
+        let request_bytes = request.len() as u64;
         let response = self
             .build_http_client(proxy_config, timeout)?
             .post(rpc)
-            .json(body)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request)
             .send()
             .await;
+        // plain http rpcs get the proxy authentication response from the proxy itself
+        let proxy_rejected = proxy_config.is_some()
+            && response.as_ref().is_ok_and(|response| {
+                response.status() == StatusCode::PROXY_AUTHENTICATION_REQUIRED
+            });
+        if response.is_ok() && !proxy_rejected {
+            self.spend_budget(rpc, body).await;
+        }
 
         match response {
+            Ok(_) if proxy_rejected => Err(EvmRpcError::Proxy(String::from(
+                "proxy authentication required",
+            ))),
             Ok(response) => {
-                if response.status().is_success() {
-                    return response
-                        .json::<Value>()
-                        .await
-                        .map_err(|err| EvmRpcError::Internal(format!("parse error: {err}")));
-                } else if response.status().is_client_error() {
-                    return Err(EvmRpcError::Client);
-                } else if response.status().is_server_error() {
-                    return Err(EvmRpcError::Server);
+                let status = response.status();
+                let content = response.bytes().await;
+                if let Some(proxy_config) = proxy_config {
+                    let response_bytes = content.as_ref().map_or(0, |content| content.len() as u64);
+                    self.record_bandwidth(
+                        chain_id,
+                        rpc,
+                        proxy_config,
+                        request_bytes,
+                        response_bytes,
+                    )
+                    .await;
+                }
+
+                if status.is_success() {
+                    let content = content
+                        .map_err(|err| EvmRpcError::Internal(format!("read error: {err}")))?;
+                    serde_json::from_slice::<Value>(&content)
+                        .map_err(|err| EvmRpcError::Internal(format!("parse error: {err}")))
+                } else if status.is_client_error() {
+                    Err(EvmRpcError::Client)
+                } else if status.is_server_error() {
+                    Err(EvmRpcError::Server)
                 } else {
-                    return Err(EvmRpcError::Internal(format!(
-                        "unknown error: {}",
-                        response.status()
-                    )));
+                    Err(EvmRpcError::Internal(format!("unknown error: {status}")))
                 }
             }
             Err(err) => {
                 if err.is_timeout() {
-                    return Err(EvmRpcError::Timeout);
+                    Err(EvmRpcError::Timeout)
+                } else if proxy_config.is_some() && is_proxy_error(&err) {
+                    Err(EvmRpcError::Proxy(format!("{err}")))
                 } else {
-                    return Err(EvmRpcError::Internal(format!("unknow error: {err}")));
+                    Err(EvmRpcError::Internal(format!("unknow error: {err}")))
                 }
             }
         }
     }
 
+    // opens a new connection per request, websocket rpcs are requested directly without proxy
+    pub async fn ws_request(
+        &self,
+        rpc: &str,
+        body: &Value,
+        timeout: Duration,
+    ) -> Result<Value, EvmRpcError> {
+        let request = async {
+            let (mut stream, _) = connect_async(rpc)
+                .await
+                .map_err(|err| EvmRpcError::Internal(format!("handshake error: {err}")))?;
+            stream
+                .send(Message::Text(body.to_string()))
+                .await
+                .map_err(|err| EvmRpcError::Internal(format!("send error: {err}")))?;
+            self.spend_budget(rpc, body).await;
+
+            while let Some(message) = stream.next().await {
+                let data = match message {
+                    Ok(Message::Text(text)) => text.into_bytes(),
+                    Ok(Message::Binary(data)) => data,
+                    Ok(Message::Close(_)) => break,
+                    Ok(_) => continue,
+                    Err(err) => return Err(EvmRpcError::Internal(format!("receive error: {err}"))),
+                };
+                let _ = stream.close(None).await;
+                return serde_json::from_slice::<Value>(&data)
+                    .map_err(|err| EvmRpcError::Internal(format!("parse error: {err}")));
+            }
+
+            Err(EvmRpcError::Server)
+        };
+
+        time::timeout(timeout, request)
+            .await
+            .map_err(|_| EvmRpcError::Timeout)?
+    }
+
+    // every request which reached the rpc is charged, including failed ones
+    async fn spend_budget(&self, rpc: &str, body: &Value) {
+        self.budget_repo
+            .write()
+            .await
+            .spend(rpc, body, unix_timestamp());
+    }
+
+    async fn record_bandwidth(
+        &self,
+        chain_id: &str,
+        rpc: &str,
+        proxy_config: &ProxyConfig,
+        request_bytes: u64,
+        response_bytes: u64,
+    ) {
+        self.proxy_service
+            .record_bandwidth(chain_id, rpc, proxy_config, request_bytes, response_bytes)
+            .await;
+    }
+
+    async fn rpc_call(
+        &self,
+        chain_id: &str,
+        rpc: &str,
+        proxy_config: Option<&ProxyConfig>,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, EvmRpcError> {
+        let request = json!({
+            "method": method,
+            "params": params,
+            "id": 1,
+            "jsonrpc": "2.0",
+        });
+
+        let mut response = self
+            .rpc_request(chain_id, rpc, proxy_config, &request, timeout)
+            .await?;
+        match response.get_mut("result") {
+            Some(result) => Ok(result.take()),
+            None => Err(EvmRpcError::Internal(format!(
+                "no result for {method}: {response}"
+            ))),
+        }
+    }
+
+    pub async fn get_block_number(
+        &self,
+        chain_id: &str,
+        rpc: &str,
+        proxy_config: Option<&ProxyConfig>,
+        timeout: Duration,
+    ) -> Result<u64, EvmRpcError> {
+        let result = self
+            .rpc_call(
+                chain_id,
+                rpc,
+                proxy_config,
+                "eth_blockNumber",
+                json!([]),
+                timeout,
+            )
+            .await?;
+
+        result
+            .as_str()
+            .and_then(|val| val.strip_prefix("0x"))
+            .and_then(|val| u64::from_str_radix(val, 16).ok())
+            .ok_or(EvmRpcError::Internal(format!(
+                "invalid eth_blockNumber result: {result}"
+            )))
+    }
+
+    pub async fn get_block_hash(
+        &self,
+        chain_id: &str,
+        rpc: &str,
+        proxy_config: Option<&ProxyConfig>,
+        height: u64,
+        timeout: Duration,
+    ) -> Result<Option<String>, EvmRpcError> {
+        let result = self
+            .rpc_call(
+                chain_id,
+                rpc,
+                proxy_config,
+                "eth_getBlockByNumber",
+                json!([format!("0x{height:x}"), false]),
+                timeout,
+            )
+            .await?;
+
+        if result.is_null() {
+            return Ok(None);
+        }
+
+        result
+            .get("hash")
+            .and_then(Value::as_str)
+            .map(|hash| Some(hash.to_lowercase()))
+            .ok_or(EvmRpcError::Internal(format!(
+                "invalid eth_getBlockByNumber result: {result}"
+            )))
+    }
+
     pub async fn rpc_health_check(
         &self,
         chain_id: &str,
@@ -148,7 +418,8 @@ impl EvmRpcService {
         proxy_config: Option<&ProxyConfig>,
         timeout: Duration,
         request_tries: u32,
-    ) -> anyhow::Result<RpcMetrics> {
+        success_threshold: f32,
+    ) -> Result<RpcMetrics, EvmRpcError> {
         let test_request = json!({
             "method": "eth_chainId",
             "params": [],
@@ -157,14 +428,18 @@ impl EvmRpcService {
         });
 
         let mut total_time = 0;
-        let mut failed = 0;
+        let mut failed_probes: Vec<String> = Vec::new();
 
         for _ in 0..request_tries {
             let start = Instant::now();
 
-            let response = self
-                .rpc_request(rpc, proxy_config, &test_request, timeout)
-                .await;
+            let response = match Transport::from_url(rpc) {
+                Transport::Http => {
+                    self.rpc_request(chain_id, rpc, proxy_config, &test_request, timeout)
+                        .await
+                }
+                Transport::Ws => self.ws_request(rpc, &test_request, timeout).await,
+            };
 
             let elapsed = start.elapsed();
 
@@ -177,18 +452,20 @@ impl EvmRpcService {
                         .map(|val| format!("{val}"));
 
                     let Some(real_chain_id) = result else {
-                        failed += 1;
+                        failed_probes.push(String::from("invalid eth_chainId response"));
                         continue;
                     };
 
                     if real_chain_id != chain_id {
-                        failed += 1;
+                        failed_probes.push(format!("wrong chain id: {real_chain_id}"));
                         continue;
                     }
                 }
+                // the check says nothing about the rpc when the proxy fails
+                Err(err @ EvmRpcError::Proxy(_)) => return Err(err),
                 Err(err) => {
-                    failed += 1;
                     log::debug!("failed to check rpc {rpc}: {err}");
+                    failed_probes.push(format!("{err}"));
                     continue;
                 }
             }
@@ -196,26 +473,421 @@ impl EvmRpcService {
             total_time += elapsed.as_millis();
         }
 
-        if failed > 0 {
-            bail!("Too many failed attempts")
+        let succeeded = request_tries - failed_probes.len() as u32;
+        let reliability = succeeded as f32 / request_tries.max(1) as f32;
+        if succeeded == 0 || reliability < success_threshold {
+            return Err(EvmRpcError::Unhealthy(format!(
+                "too many failed attempts ({}/{request_tries}): {}",
+                failed_probes.len(),
+                failed_probes.join(", ")
+            )));
         }
 
-        let response_time_ms = total_time / (request_tries - failed) as u128;
-        return Ok(RpcMetrics { response_time_ms });
+        Ok(RpcMetrics {
+            response_time_ms: total_time / succeeded as u128,
+            reliability,
+            failed_probes,
+            last_probed_at: Some(unix_timestamp()),
+        })
     }
 
-    pub async fn fetch_rpcs(&self) -> anyhow::Result<HashMap<String, Vec<String>>> {
-        self.chainlist_client.fetch_rpcs().await
+    pub async fn fetch_rpcs(
+        &self,
+        source_upstreams: &mut HashMap<String, ChainToUpstreams>,
+    ) -> anyhow::Result<ChainToUpstreams> {
+        fetch_from_sources(&self.rpc_sources, source_upstreams).await
     }
 
-    pub async fn set_rpcs_for_chain_id(&self, chain_id: &str, rpcs: Vec<(String, RpcMetrics)>) {
+    pub async fn set_rpcs_for_chain_id(
+        &self,
+        chain_id: &str,
+        transport: Transport,
+        rpcs: Vec<(Upstream, RpcMetrics)>,
+    ) {
         self.cache_repo
             .write()
             .await
-            .set_rpcs_for_chain_id(chain_id, rpcs)
+            .set_rpcs_for_chain_id(chain_id, transport, rpcs)
     }
 
-    pub async fn get_rpcs_for_chain_id(&self, chain_id: &str) -> Option<Vec<(String, RpcMetrics)>> {
-        self.cache_repo.read().await.get_rpcs_for_chain_id(chain_id)
+    pub async fn get_rpcs_for_chain_id(
+        &self,
+        chain_id: &str,
+        transport: Transport,
+    ) -> Option<Vec<(Upstream, RpcMetrics)>> {
+        self.cache_repo
+            .read()
+            .await
+            .get_rpcs_for_chain_id(chain_id, transport)
+    }
+
+    pub async fn count_healthy_rpcs(&self, chain_id: &str, transport: Transport) -> usize {
+        self.get_rpcs_for_chain_id(chain_id, transport)
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter(|(_, metrics)| metrics.last_probed_at.is_some())
+            .count()
+    }
+
+    pub async fn set_banned_for_chain_id(&self, chain_id: &str, banned: Vec<BannedUpstream>) {
+        self.cache_repo
+            .write()
+            .await
+            .set_banned_for_chain_id(chain_id, banned)
+    }
+
+    pub async fn get_banned_for_chain_id(&self, chain_id: &str) -> Vec<BannedUpstream> {
+        self.cache_repo
+            .read()
+            .await
+            .get_banned_for_chain_id(chain_id)
+    }
+
+    pub async fn set_budgets(&self, upstreams: &[Upstream]) {
+        let rpc_to_budget: HashMap<String, Budget> = upstreams
+            .iter()
+            .filter_map(|upstream| {
+                let budget = upstream.budget.clone()?;
+                Some((upstream.url.clone(), budget))
+            })
+            .collect();
+        self.budget_repo.write().await.set_budgets(rpc_to_budget);
+    }
+
+    pub async fn is_budget_exhausted(&self, rpc: &str) -> bool {
+        self.budget_repo
+            .read()
+            .await
+            .is_exhausted(rpc, unix_timestamp())
+    }
+
+    pub async fn get_budget_spends(&self) -> HashMap<String, BudgetSpend> {
+        self.budget_repo.read().await.get_spends()
+    }
+
+    pub async fn restore_budget_spends(&self, rpc_to_spend: HashMap<String, BudgetSpend>) {
+        self.budget_repo.write().await.restore_spends(rpc_to_spend);
+    }
+
+    // operator overrides of the upstream and then of the chain take precedence over measurements
+    // websocket rpcs are always requested directly
+    pub async fn get_egress(&self, chain_id: &str, upstream: &Upstream) -> Egress {
+        if upstream.transport == Transport::Ws {
+            return Egress::Direct;
+        }
+        if let Some(egress) = upstream
+            .egress
+            .or(self.config_repo.egress_policy_for(chain_id).pinned())
+        {
+            return egress;
+        }
+
+        self.egress_repo
+            .read()
+            .await
+            .get_egress(&upstream.url)
+            .unwrap_or(Egress::Proxied)
+    }
+
+    pub async fn record_egress(
+        &self,
+        rpc: &str,
+        direct: Option<Duration>,
+        proxied: Option<Duration>,
+    ) {
+        let changed = self.egress_repo.write().await.record(
+            rpc,
+            direct,
+            proxied,
+            self.config_repo.egress_latency_ratio,
+        );
+        if let Some(egress) = changed {
+            log::info!("egress of {rpc} is {egress:?}, direct: {direct:?}, proxied: {proxied:?}");
+        }
+    }
+
+    pub async fn record_traffic(
+        &self,
+        chain_id: &str,
+        rpc: &str,
+        method_class: MethodClass,
+        country: Option<&str>,
+        elapsed: Duration,
+        success: bool,
+    ) {
+        let mut traffic_repo = self.traffic_repo.write().await;
+        let latency_ms = elapsed.as_millis() as f32;
+        let alpha = self.config_repo.traffic_ewma_alpha;
+        traffic_repo.record(chain_id, rpc, method_class, latency_ms, success, alpha);
+        if let Some(country) = country {
+            traffic_repo.record_country(chain_id, rpc, country, latency_ms, success, alpha);
+        }
+    }
+
+    pub async fn get_country_traffic_for_chain_id(
+        &self,
+        chain_id: &str,
+    ) -> HashMap<String, Vec<CountryTraffic>> {
+        self.traffic_repo
+            .read()
+            .await
+            .get_country_traffic_for_chain_id(chain_id)
+    }
+
+    pub async fn get_traffic_for_chain_id(
+        &self,
+        chain_id: &str,
+    ) -> HashMap<String, Vec<TrafficMetrics>> {
+        self.traffic_repo
+            .read()
+            .await
+            .get_traffic_for_chain_id(chain_id)
+    }
+
+    // tiers are drained in order, rpcs with a spent budget are demoted below every tier,
+    // inside of a tier probe ranking is reordered by live traffic of the same method class
+    pub async fn get_ranked_rpcs_for_chain_id(
+        &self,
+        chain_id: &str,
+        method_class: MethodClass,
+    ) -> Option<Vec<(Upstream, RpcMetrics)>> {
+        let rpcs = self
+            .get_rpcs_for_chain_id(chain_id, Transport::Http)
+            .await?;
+        let traffic_repo = self.traffic_repo.read().await;
+        let budget_repo = self.budget_repo.read().await;
+        let timestamp = unix_timestamp();
+
+        let scored_rpcs: Vec<_> = rpcs
+            .into_iter()
+            .map(|(upstream, metrics)| {
+                let traffic_metrics =
+                    traffic_repo.get_traffic(chain_id, &upstream.url, method_class);
+                let score = self.blend_score(&metrics, traffic_metrics);
+                let exhausted = budget_repo.is_exhausted(&upstream.url, timestamp);
+                (exhausted, score, (upstream, metrics))
+            })
+            .collect();
+
+        Some(rank_rpcs(scored_rpcs))
+    }
+
+    fn blend_score(&self, metrics: &RpcMetrics, traffic_metrics: Option<&TrafficMetrics>) -> f32 {
+        let probe_score = metrics.to_score();
+        let Some(traffic_metrics) = traffic_metrics else {
+            return probe_score;
+        };
+
+        let warmup = (traffic_metrics.requests as f32 / TRAFFIC_WARMUP_REQUESTS as f32).min(1.0);
+        let weight = match metrics.last_probed_at {
+            Some(_) => self.config_repo.traffic_weight * warmup,
+            None => warmup,
+        };
+
+        weight * traffic_metrics.score() + (1.0 - weight) * probe_score
+    }
+}
+
+// rpcs with a spent budget go last, then tiers in order and scores inside of a tier
+fn rank_rpcs(
+    mut scored_rpcs: Vec<(bool, f32, (Upstream, RpcMetrics))>,
+) -> Vec<(Upstream, RpcMetrics)> {
+    scored_rpcs.sort_by(|(a_exhausted, a, a_rpc), (b_exhausted, b, b_rpc)| {
+        (a_exhausted, a_rpc.0.tier)
+            .cmp(&(b_exhausted, b_rpc.0.tier))
+            .then_with(|| b.total_cmp(a))
+    });
+
+    scored_rpcs.into_iter().map(|(_, _, rpc)| rpc).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Proxy;
+    use rocket::tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task,
+    };
+
+    use super::*;
+    use crate::models::upstream::UpstreamTier;
+
+    fn scored(
+        url: &str,
+        tier: UpstreamTier,
+        exhausted: bool,
+        score: f32,
+    ) -> (bool, f32, (Upstream, RpcMetrics)) {
+        let mut upstream = Upstream::new(url, "test");
+        upstream.tier = tier;
+        (exhausted, score, (upstream, RpcMetrics::unprobed()))
+    }
+
+    fn urls(ranked: Vec<(Upstream, RpcMetrics)>) -> Vec<String> {
+        ranked
+            .into_iter()
+            .map(|(upstream, _)| upstream.url)
+            .collect()
+    }
+
+    #[test]
+    fn ranks_tier_before_score() {
+        let ranked = rank_rpcs(vec![
+            scored("https://overflow.rpc", UpstreamTier::Overflow, false, 0.9),
+            scored("https://primary.rpc", UpstreamTier::Primary, false, 0.1),
+            scored(
+                "https://secondary-slow.rpc",
+                UpstreamTier::Secondary,
+                false,
+                0.3,
+            ),
+            scored(
+                "https://secondary-fast.rpc",
+                UpstreamTier::Secondary,
+                false,
+                0.8,
+            ),
+        ]);
+
+        assert_eq!(
+            urls(ranked),
+            vec![
+                "https://primary.rpc",
+                "https://secondary-fast.rpc",
+                "https://secondary-slow.rpc",
+                "https://overflow.rpc",
+            ]
+        );
+    }
+
+    #[test]
+    fn ranks_exhausted_last() {
+        let ranked = rank_rpcs(vec![
+            scored(
+                "https://primary-spent.rpc",
+                UpstreamTier::Primary,
+                true,
+                0.9,
+            ),
+            scored("https://overflow.rpc", UpstreamTier::Overflow, false, 0.1),
+            scored(
+                "https://overflow-spent.rpc",
+                UpstreamTier::Overflow,
+                true,
+                0.9,
+            ),
+            scored("https://primary.rpc", UpstreamTier::Primary, false, 0.2),
+        ]);
+
+        assert_eq!(
+            urls(ranked),
+            vec![
+                "https://primary.rpc",
+                "https://overflow.rpc",
+                "https://primary-spent.rpc",
+                "https://overflow-spent.rpc",
+            ]
+        );
+    }
+
+    // answers the first request of one connection and closes it
+    async fn mock_proxy(reply: &'static [u8], socks: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            if socks {
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(&[5, 0]).await;
+            }
+            let _ = stream.read(&mut buf).await;
+            let _ = stream.write_all(reply).await;
+        });
+
+        address.to_string()
+    }
+
+    async fn proxied_error(proxy: &str, rpc: &str) -> reqwest::Error {
+        Client::builder()
+            .proxy(Proxy::all(proxy).unwrap())
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap()
+            .post(rpc)
+            .send()
+            .await
+            .unwrap_err()
+    }
+
+    #[test]
+    fn classifies_proxy_failures() {
+        assert!(is_proxy_failure(
+            "tcp connect error: Connection refused (os error 111)"
+        ));
+        assert!(is_proxy_failure("dns error"));
+        assert!(is_proxy_failure("proxy authentication required"));
+        assert!(is_proxy_failure(
+            "socks connect error: Connection refused (os error 111)"
+        ));
+        assert!(is_proxy_failure(
+            "socks connect error: Password auth failure, code: 1"
+        ));
+        assert!(is_proxy_failure(
+            "socks connect error: No acceptable auth methods"
+        ));
+    }
+
+    #[test]
+    fn keeps_upstream_failures() {
+        assert!(!is_proxy_failure("unsuccessful tunnel"));
+        assert!(!is_proxy_failure("unexpected eof while tunneling"));
+        assert!(!is_proxy_failure("connection reset by peer"));
+        assert!(!is_proxy_failure("socks connect error: Host unreachable"));
+        assert!(!is_proxy_failure("socks connect error: Connection refused"));
+        assert!(!is_proxy_failure(
+            "socks connect error: General SOCKS server failure"
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn unreachable_proxy_is_proxy_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let err = proxied_error(&format!("http://{address}"), "http://upstream.invalid").await;
+
+        assert!(is_proxy_error(&err));
+    }
+
+    #[rocket::async_test]
+    async fn rejected_proxy_auth_is_proxy_error() {
+        let proxy = mock_proxy(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n", false).await;
+
+        let err = proxied_error(&format!("http://{proxy}"), "https://upstream.invalid").await;
+
+        assert!(is_proxy_error(&err));
+    }
+
+    #[rocket::async_test]
+    async fn refused_tunnel_is_upstream_error() {
+        let proxy = mock_proxy(b"HTTP/1.1 502 Bad Gateway\r\n\r\n", false).await;
+
+        let err = proxied_error(&format!("http://{proxy}"), "https://upstream.invalid").await;
+
+        assert!(!is_proxy_error(&err));
+    }
+
+    #[rocket::async_test]
+    async fn unreachable_socks_target_is_upstream_error() {
+        // reply code 4 is host unreachable
+        let proxy = mock_proxy(&[5, 4, 0, 1, 0, 0, 0, 0, 0, 0], true).await;
+
+        let err = proxied_error(&format!("socks5h://{proxy}"), "http://upstream.invalid").await;
+
+        assert!(!is_proxy_error(&err));
     }
 }
@@ -0,0 +1,93 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use rocket::tokio::sync::RwLock;
+
+use crate::{
+    client::source::ChainToUpstreams,
+    models::history::HistoryPoint,
+    repo::history::{HistoryRepo, RpcHistory},
+    services::evm_rpc::RpcMetrics,
+    util::{stable_id, unix_timestamp},
+};
+
+pub struct HistoryService {
+    history_repo: Arc<RwLock<HistoryRepo>>,
+}
+
+impl HistoryService {
+    pub fn new(history_repo: Arc<RwLock<HistoryRepo>>) -> Self {
+        Self { history_repo }
+    }
+
+    async fn rpc_history(&self, chain_id: &str, rpc: &str) -> Arc<Mutex<RpcHistory>> {
+        let history = self
+            .history_repo
+            .read()
+            .await
+            .get_rpc_history(chain_id, rpc);
+        match history {
+            Some(history) => history,
+            None => self
+                .history_repo
+                .write()
+                .await
+                .add_rpc_history(chain_id, rpc),
+        }
+    }
+
+    pub async fn record_check(&self, chain_id: &str, rpc: &str, metrics: Option<&RpcMetrics>) {
+        self.rpc_history(chain_id, rpc)
+            .await
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record_check(
+                unix_timestamp(),
+                metrics.map(|metrics| (metrics.response_time_ms, metrics.reliability)),
+            );
+    }
+
+    pub async fn record_request(
+        &self,
+        chain_id: &str,
+        rpc: &str,
+        elapsed: Duration,
+        success: bool,
+    ) {
+        self.rpc_history(chain_id, rpc)
+            .await
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record_request(unix_timestamp(), elapsed.as_millis(), success);
+    }
+
+    // histories are kept for every fed upstream, banned and filtered ones included
+    pub async fn retain(&self, chain_to_rpc: &ChainToUpstreams) {
+        let chain_id_to_rpcs: HashMap<String, HashSet<String>> = chain_to_rpc
+            .iter()
+            .map(|(chain_id, rpcs)| {
+                (
+                    chain_id.clone(),
+                    rpcs.iter().map(|rpc| stable_id(&rpc.url)).collect(),
+                )
+            })
+            .collect();
+        self.history_repo.write().await.retain(&chain_id_to_rpcs);
+    }
+
+    pub async fn get_history(
+        &self,
+        chain_id: &str,
+        rpc_id: &str,
+        from: u64,
+        to: u64,
+    ) -> Option<(String, Vec<HistoryPoint>)> {
+        self.history_repo
+            .read()
+            .await
+            .get_history(chain_id, rpc_id, from, to)
+    }
+}
@@ -1,3 +1,8 @@
+pub mod chain;
 pub mod evm_rpc;
+pub mod history;
 pub mod monitoring;
+pub mod probe;
 pub mod proxy;
+pub mod rule;
+pub mod snapshot;
@@ -2,15 +2,26 @@ use std::sync::Arc;
 
 use rocket::tokio::sync::RwLock;
 
-use crate::{models::monitoring::Monitoring, repo::cache::CacheRepo};
+use crate::{
+    models::{
+        budget::BudgetStatus,
+        monitoring::{ForkIncident, Monitoring},
+    },
+    repo::{budget::BudgetRepo, cache::CacheRepo},
+    util::unix_timestamp,
+};
 
 pub struct MonitoringService {
     cache_repo: Arc<RwLock<CacheRepo>>,
+    budget_repo: Arc<RwLock<BudgetRepo>>,
 }
 
 impl MonitoringService {
-    pub fn new(cache_repo: Arc<RwLock<CacheRepo>>) -> Self {
-        Self { cache_repo }
+    pub fn new(cache_repo: Arc<RwLock<CacheRepo>>, budget_repo: Arc<RwLock<BudgetRepo>>) -> Self {
+        Self {
+            cache_repo,
+            budget_repo,
+        }
     }
 
     pub async fn get_monitoring(&self) -> Monitoring {
@@ -31,4 +42,13 @@ impl MonitoringService {
         let mut cache = self.cache_repo.write().await;
         cache.get_monitoring_mut().error_income_requests += 1;
     }
+
+    pub async fn record_fork_incident(&self, incident: ForkIncident) {
+        let mut cache = self.cache_repo.write().await;
+        cache.get_monitoring_mut().push_fork_incident(incident);
+    }
+
+    pub async fn get_budget_statuses(&self) -> Vec<BudgetStatus> {
+        self.budget_repo.read().await.get_statuses(unix_timestamp())
+    }
 }
@@ -0,0 +1,902 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
+use futures::future::join_all;
+use rocket::tokio::{
+    sync::{Mutex, Semaphore, SemaphorePermit},
+    task,
+    time::{self, Instant},
+};
+
+use crate::{
+    models::{
+        egress::{Egress, EgressPolicy},
+        monitoring::{ForkBranch, ForkIncident},
+        proxy::ProxyConfig,
+        upstream::{Transport, Upstream},
+    },
+    repo::config::ConfigRepo,
+    services::{
+        evm_rpc::{EvmRpcError, EvmRpcService, RpcMetrics},
+        history::HistoryService,
+        monitoring::MonitoringService,
+        proxy::ProxyService,
+    },
+    util::unix_timestamp,
+};
+
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_BACKOFF_EXPONENT: u32 = 16;
+// fork check needs a meaningful majority
+const MIN_FORK_CHECK_RPCS: usize = 3;
+
+enum ProbeState {
+    Pending,
+    Healthy(RpcMetrics),
+    Failing,
+}
+
+struct ProbeTarget {
+    upstream: Upstream,
+    state: ProbeState,
+    next_probe_at: Instant,
+    consecutive_failures: u32,
+    in_flight: bool,
+    quarantined_until: Option<Instant>,
+}
+
+impl ProbeTarget {
+    fn is_quarantined(&self, now: Instant) -> bool {
+        self.quarantined_until
+            .is_some_and(|quarantined_until| quarantined_until > now)
+    }
+}
+
+// updated on every request to the chain without locking the probes
+#[derive(Default)]
+struct ChainActivity {
+    // discovered chains are probed on the lazy schedule until they get requests
+    lazy: AtomicBool,
+    // unix timestamp of the last request, zero when the chain was never requested
+    requested_at: AtomicU64,
+}
+
+impl ChainActivity {
+    fn is_idle(&self, timestamp: u64, lazy_probe_interval: Duration) -> bool {
+        self.lazy.load(Ordering::Relaxed)
+            && is_idle_since(
+                self.requested_at.load(Ordering::Relaxed),
+                timestamp,
+                lazy_probe_interval,
+            )
+    }
+}
+
+fn is_idle_since(requested_at: u64, timestamp: u64, lazy_probe_interval: Duration) -> bool {
+    requested_at == 0 || timestamp.saturating_sub(requested_at) > lazy_probe_interval.as_secs()
+}
+
+struct ChainProbes {
+    semaphore: Arc<Semaphore>,
+    targets: HashMap<String, ProbeTarget>,
+    activity: Arc<ChainActivity>,
+}
+
+impl ChainProbes {
+    fn new(concurrency: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(concurrency)),
+            targets: HashMap::new(),
+            activity: Arc::new(ChainActivity::default()),
+        }
+    }
+
+    fn is_idle(&self, lazy_probe_interval: Duration) -> bool {
+        self.activity.is_idle(unix_timestamp(), lazy_probe_interval)
+    }
+}
+
+pub struct ProbeService {
+    evm_rpc_service: Arc<EvmRpcService>,
+    history_service: Arc<HistoryService>,
+    monitoring_service: Arc<MonitoringService>,
+    proxy_service: Arc<ProxyService>,
+    config_repo: ConfigRepo,
+    semaphore: Arc<Semaphore>,
+    chains: Mutex<HashMap<String, ChainProbes>>,
+    // activities of the chains, read by requests without locking the chains
+    activities: ArcSwap<HashMap<String, Arc<ChainActivity>>>,
+}
+
+impl ProbeService {
+    pub fn new(
+        evm_rpc_service: Arc<EvmRpcService>,
+        history_service: Arc<HistoryService>,
+        monitoring_service: Arc<MonitoringService>,
+        proxy_service: Arc<ProxyService>,
+        config_repo: ConfigRepo,
+    ) -> Self {
+        Self {
+            evm_rpc_service,
+            history_service,
+            monitoring_service,
+            proxy_service,
+            semaphore: Arc::new(Semaphore::new(config_repo.probe_concurrency)),
+            config_repo,
+            chains: Mutex::new(HashMap::new()),
+            activities: ArcSwap::from_pointee(HashMap::new()),
+        }
+    }
+
+    // chains are published to the activities when they are created, they are never removed
+    fn chain_mut<'a>(
+        &self,
+        chains: &'a mut HashMap<String, ChainProbes>,
+        chain_id: &str,
+    ) -> &'a mut ChainProbes {
+        chains.entry(chain_id.to_owned()).or_insert_with(|| {
+            let chain = ChainProbes::new(self.config_repo.probe_chain_concurrency);
+            self.activities.rcu(|activities| {
+                let mut activities = HashMap::clone(activities);
+                activities.insert(chain_id.to_owned(), chain.activity.clone());
+                activities
+            });
+            chain
+        })
+    }
+
+    pub async fn sync_targets(&self, chain_id: &str, upstreams: &[Upstream], lazy: bool) {
+        let rankings = {
+            let mut chains = self.chains.lock().await;
+            let chain = self.chain_mut(&mut chains, chain_id);
+            chain.activity.lazy.store(lazy, Ordering::Relaxed);
+
+            let now = Instant::now();
+            let next_probe_at = if chain.is_idle(self.config_repo.lazy_probe_interval) {
+                now + self.config_repo.lazy_probe_interval
+            } else {
+                now
+            };
+
+            chain
+                .targets
+                .retain(|rpc, _| upstreams.iter().any(|upstream| &upstream.url == rpc));
+            for upstream in upstreams {
+                chain
+                    .targets
+                    .entry(upstream.url.clone())
+                    .and_modify(|target| target.upstream = upstream.clone())
+                    .or_insert_with(|| ProbeTarget {
+                        upstream: upstream.clone(),
+                        state: ProbeState::Pending,
+                        next_probe_at,
+                        consecutive_failures: 0,
+                        in_flight: false,
+                        quarantined_until: None,
+                    });
+            }
+
+            build_rankings(&chain.targets)
+        };
+
+        self.set_rankings(chain_id, rankings).await;
+    }
+
+    // seeds targets with previously ranked rpcs, so they are served with their last metrics
+    // until the first probes complete
+    pub async fn restore_targets(
+        &self,
+        chain_id: &str,
+        rpcs: Vec<(Upstream, RpcMetrics)>,
+        lazy: bool,
+    ) {
+        let rankings = {
+            let mut chains = self.chains.lock().await;
+            let chain = self.chain_mut(&mut chains, chain_id);
+            chain.activity.lazy.store(lazy, Ordering::Relaxed);
+
+            let now = Instant::now();
+            let next_probe_at = if chain.is_idle(self.config_repo.lazy_probe_interval) {
+                now + self.config_repo.lazy_probe_interval
+            } else {
+                now
+            };
+
+            for (upstream, metrics) in rpcs {
+                let state = match metrics.last_probed_at {
+                    Some(_) => ProbeState::Healthy(metrics),
+                    None => ProbeState::Pending,
+                };
+                chain
+                    .targets
+                    .entry(upstream.url.clone())
+                    .or_insert_with(|| ProbeTarget {
+                        upstream,
+                        state,
+                        next_probe_at,
+                        consecutive_failures: 0,
+                        in_flight: false,
+                        quarantined_until: None,
+                    });
+            }
+
+            build_rankings(&chain.targets)
+        };
+
+        self.set_rankings(chain_id, rankings).await;
+    }
+
+    // wakes up probes of an idle lazy chain, called on every request to the chain.
+    // probes are locked only by the first request after an idle period
+    pub async fn request_probes(&self, chain_id: &str) {
+        let Some(activity) = self.activities.load().get(chain_id).cloned() else {
+            return;
+        };
+        if !activity.lazy.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let timestamp = unix_timestamp();
+        let requested_at = activity.requested_at.swap(timestamp, Ordering::Relaxed);
+        if !is_idle_since(
+            requested_at,
+            timestamp,
+            self.config_repo.lazy_probe_interval,
+        ) {
+            return;
+        }
+
+        log::info!("start probing of {chain_id} on request");
+        let mut chains = self.chains.lock().await;
+        let Some(chain) = chains.get_mut(chain_id) else {
+            return;
+        };
+        let now = Instant::now();
+        let next_probe_at = now + self.config_repo.probe_interval;
+        for target in chain.targets.values_mut() {
+            target.next_probe_at = match target.state {
+                ProbeState::Pending => now,
+                _ => target.next_probe_at.min(next_probe_at),
+            };
+        }
+    }
+
+    // compares block hashes at a recent common height and quarantines rpcs outside of the majority,
+    // idle lazy chains are not checked until they get requests
+    pub async fn check_forks(&self, chain_id: &str) {
+        let (upstreams, chain_semaphore): (Vec<Upstream>, Arc<Semaphore>) = {
+            let now = Instant::now();
+            let chains = self.chains.lock().await;
+            let Some(chain) = chains.get(chain_id) else {
+                return;
+            };
+            if chain.is_idle(self.config_repo.lazy_probe_interval) {
+                return;
+            }
+            let upstreams = chain
+                .targets
+                .iter()
+                .filter(|(_, target)| {
+                    target.upstream.transport == Transport::Http
+                        && matches!(target.state, ProbeState::Healthy(_))
+                        && !target.is_quarantined(now)
+                })
+                .map(|(_, target)| target.upstream.clone())
+                .collect();
+            (upstreams, chain.semaphore.clone())
+        };
+        if upstreams.len() < MIN_FORK_CHECK_RPCS {
+            return;
+        }
+
+        let mut rpcs: Vec<String> = Vec::new();
+        let mut rpc_proxies: Vec<Option<ProxyConfig>> = Vec::new();
+        for upstream in &upstreams {
+            rpcs.push(upstream.url.clone());
+            rpc_proxies.push(self.proxy_for(chain_id, upstream).await);
+        }
+        let timeout = self.config_repo.feed_max_timeout;
+
+        let chain_semaphore = chain_semaphore.as_ref();
+        let heights: Vec<u64> = join_all(rpcs.iter().zip(&rpc_proxies).map(
+            |(rpc, proxy_config)| async move {
+                let _permits = self.acquire_permits(chain_semaphore).await?;
+                self.evm_rpc_service
+                    .get_block_number(chain_id, rpc, proxy_config.as_ref(), timeout)
+                    .await
+                    .ok()
+            },
+        ))
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+        let Some(height) = common_height(heights, self.config_repo.fork_check_depth) else {
+            return;
+        };
+
+        let hashes = join_all(rpcs.iter().zip(&rpc_proxies).map(
+            |(rpc, proxy_config)| async move {
+                let Some(_permits) = self.acquire_permits(chain_semaphore).await else {
+                    return (rpc, Ok(None));
+                };
+                let hash = self
+                    .evm_rpc_service
+                    .get_block_hash(chain_id, rpc, proxy_config.as_ref(), height, timeout)
+                    .await;
+                (rpc, hash)
+            },
+        ))
+        .await;
+
+        let mut hash_to_rpcs: HashMap<String, Vec<String>> = HashMap::new();
+        for (rpc, hash) in hashes {
+            match hash {
+                Ok(Some(hash)) => hash_to_rpcs.entry(hash).or_default().push(rpc.clone()),
+                // rpc is behind the common height
+                Ok(None) => {}
+                Err(err) => log::debug!("failed to get block {height} from {rpc}: {err}"),
+            }
+        }
+        let Some((branches, quarantined_rpcs)) = split_branches(hash_to_rpcs) else {
+            return;
+        };
+        if quarantined_rpcs.is_empty() {
+            log::warn!("no majority block hash at {height} for {chain_id}");
+        }
+
+        log::warn!(
+            "block hash mismatch at {height} for {chain_id}, quarantined: {quarantined_rpcs:?}"
+        );
+        self.quarantine(chain_id, &quarantined_rpcs).await;
+        self.monitoring_service
+            .record_fork_incident(ForkIncident {
+                chain_id: chain_id.to_owned(),
+                height,
+                timestamp: unix_timestamp(),
+                branches,
+                quarantined_rpcs,
+            })
+            .await;
+    }
+
+    // requests to upstreams are bounded per chain first and then globally,
+    // none when the probes are stopped
+    async fn acquire_permits<'a>(
+        &'a self,
+        chain_semaphore: &'a Semaphore,
+    ) -> Option<(SemaphorePermit<'a>, SemaphorePermit<'a>)> {
+        let chain_permit = chain_semaphore.acquire().await.ok()?;
+        let permit = self.semaphore.acquire().await.ok()?;
+        Some((chain_permit, permit))
+    }
+
+    // probes take the same egress as the traffic
+    async fn proxy_for(&self, chain_id: &str, upstream: &Upstream) -> Option<ProxyConfig> {
+        let egress = self.evm_rpc_service.get_egress(chain_id, upstream).await;
+        self.proxy_service.proxy_for(chain_id, upstream, egress)
+    }
+
+    // requests every http upstream both directly and through the proxy pool
+    // and records the egress which serves it better
+    pub async fn measure_egress(&self, chain_id: &str) {
+        if self.config_repo.egress_policy_for(chain_id) != EgressPolicy::Auto {
+            return;
+        }
+
+        let upstreams: Vec<Upstream> = {
+            let now = Instant::now();
+            let chains = self.chains.lock().await;
+            let Some(chain) = chains.get(chain_id) else {
+                return;
+            };
+            if chain.is_idle(self.config_repo.lazy_probe_interval) {
+                return;
+            }
+            chain
+                .targets
+                .values()
+                .filter(|target| {
+                    target.upstream.transport == Transport::Http
+                        && target.upstream.egress.is_none()
+                        && !target.is_quarantined(now)
+                })
+                .map(|target| target.upstream.clone())
+                .collect()
+        };
+
+        let timeout = self.config_repo.feed_max_timeout;
+        join_all(upstreams.iter().map(|upstream| async move {
+            let rpc = &upstream.url;
+            if self
+                .proxy_service
+                .proxy_for(chain_id, upstream, Egress::Proxied)
+                .is_none()
+            {
+                return;
+            }
+            if self.evm_rpc_service.is_budget_exhausted(rpc).await {
+                return;
+            }
+            let Ok(_permit) = self.semaphore.acquire().await else {
+                return;
+            };
+
+            let measure = |proxy_config: Option<ProxyConfig>| async move {
+                let start = Instant::now();
+                self.evm_rpc_service
+                    .get_block_number(chain_id, rpc, proxy_config.as_ref(), timeout)
+                    .await
+                    .map(|_| start.elapsed())
+            };
+            let direct = measure(None).await.ok();
+            // the proxied sample fails over like the traffic, proxy failures say nothing
+            // about the upstream and leave the egress as it is
+            let (proxied, proxy_config) = self
+                .proxy_service
+                .request(chain_id, upstream, Egress::Proxied, measure)
+                .await;
+            if proxy_config.is_none() || matches!(proxied, Err(EvmRpcError::Proxy(_))) {
+                return;
+            }
+            self.evm_rpc_service
+                .record_egress(rpc, direct, proxied.ok())
+                .await;
+        }))
+        .await;
+    }
+
+    async fn quarantine(&self, chain_id: &str, rpcs: &[String]) {
+        if rpcs.is_empty() {
+            return;
+        }
+
+        let rankings = {
+            let mut chains = self.chains.lock().await;
+            let Some(chain) = chains.get_mut(chain_id) else {
+                return;
+            };
+
+            let quarantined_until = Instant::now() + self.config_repo.fork_quarantine;
+            for rpc in rpcs {
+                if let Some(target) = chain.targets.get_mut(rpc) {
+                    target.quarantined_until = Some(quarantined_until);
+                }
+            }
+
+            build_rankings(&chain.targets)
+        };
+
+        self.set_rankings(chain_id, rankings).await;
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            for (chain_id, upstream, chain_semaphore) in self.take_due_targets().await {
+                let probe_service = self.clone();
+                task::spawn(async move {
+                    probe_service
+                        .probe(chain_id, upstream, chain_semaphore)
+                        .await;
+                });
+            }
+        }
+    }
+
+    async fn take_due_targets(&self) -> Vec<(String, Upstream, Arc<Semaphore>)> {
+        let now = Instant::now();
+        let mut due_targets = Vec::new();
+
+        let mut chains = self.chains.lock().await;
+        for (chain_id, chain) in chains.iter_mut() {
+            for target in chain.targets.values_mut() {
+                if target.in_flight || target.next_probe_at > now {
+                    continue;
+                }
+
+                target.in_flight = true;
+                due_targets.push((
+                    chain_id.clone(),
+                    target.upstream.clone(),
+                    chain.semaphore.clone(),
+                ));
+            }
+        }
+
+        due_targets
+    }
+
+    async fn probe(&self, chain_id: String, upstream: Upstream, chain_semaphore: Arc<Semaphore>) {
+        let rpc = upstream.url.clone();
+        // probes are charged too, spent rpcs keep their last metrics until the budget resets
+        if self.evm_rpc_service.is_budget_exhausted(&rpc).await {
+            self.postpone_probe(&chain_id, &rpc).await;
+            return;
+        }
+
+        let Some(_permits) = self.acquire_permits(&chain_semaphore).await else {
+            self.postpone_probe(&chain_id, &rpc).await;
+            return;
+        };
+
+        let egress = self.evm_rpc_service.get_egress(&chain_id, &upstream).await;
+        let (metrics, _) = self
+            .proxy_service
+            .request(&chain_id, &upstream, egress, |proxy_config| {
+                let (chain_id, rpc) = (&chain_id, &rpc);
+                async move {
+                    self.evm_rpc_service
+                        .rpc_health_check(
+                            chain_id,
+                            rpc,
+                            proxy_config.as_ref(),
+                            self.config_repo.feed_max_timeout,
+                            self.config_repo.feed_request_tries,
+                            self.config_repo.feed_success_threshold,
+                        )
+                        .await
+                }
+            })
+            .await;
+
+        // the upstream was not reached, it keeps its state until the next probe
+        if let Err(EvmRpcError::Proxy(err)) = &metrics {
+            log::debug!("rpc {rpc} for {chain_id} was not probed: {err}");
+            self.postpone_probe(&chain_id, &rpc).await;
+            return;
+        }
+        if let Err(err) = &metrics {
+            log::debug!("rpc {rpc} for {chain_id} failed health check: {err}");
+        }
+
+        let metrics = metrics.ok();
+        self.history_service
+            .record_check(&chain_id, &rpc, metrics.as_ref())
+            .await;
+        self.record_probe(&chain_id, &rpc, metrics).await;
+    }
+
+    async fn record_probe(&self, chain_id: &str, rpc: &str, metrics: Option<RpcMetrics>) {
+        let rankings = {
+            let mut chains = self.chains.lock().await;
+            let Some(chain) = chains.get_mut(chain_id) else {
+                return;
+            };
+            let Some(target) = chain.targets.get_mut(rpc) else {
+                return;
+            };
+
+            target.in_flight = false;
+            match metrics {
+                Some(metrics) => {
+                    target.state = ProbeState::Healthy(metrics);
+                    target.consecutive_failures = 0;
+                }
+                None => {
+                    target.state = ProbeState::Failing;
+                    target.consecutive_failures += 1;
+                }
+            }
+
+            let transport = target.upstream.transport;
+            let rankings = build_rankings(&chain.targets);
+            let rank = rankings
+                .iter()
+                .find(|(ranking_transport, _)| *ranking_transport == transport)
+                .and_then(|(_, ranking)| {
+                    ranking.iter().position(|(upstream, _)| upstream.url == rpc)
+                });
+            let now = Instant::now();
+            let idle = chain.is_idle(self.config_repo.lazy_probe_interval);
+            let Some(target) = chain.targets.get_mut(rpc) else {
+                return;
+            };
+            target.next_probe_at = now + self.next_interval(target, rank, idle);
+
+            rankings
+        };
+
+        self.set_rankings(chain_id, rankings).await;
+    }
+
+    async fn set_rankings(
+        &self,
+        chain_id: &str,
+        rankings: Vec<(Transport, Vec<(Upstream, RpcMetrics)>)>,
+    ) {
+        for (transport, ranking) in rankings {
+            self.evm_rpc_service
+                .set_rpcs_for_chain_id(chain_id, transport, ranking)
+                .await;
+        }
+    }
+
+    async fn postpone_probe(&self, chain_id: &str, rpc: &str) {
+        let mut chains = self.chains.lock().await;
+        let Some(target) = chains
+            .get_mut(chain_id)
+            .and_then(|chain| chain.targets.get_mut(rpc))
+        else {
+            return;
+        };
+
+        target.in_flight = false;
+        target.next_probe_at = Instant::now() + self.config_repo.probe_interval;
+    }
+
+    fn next_interval(&self, target: &ProbeTarget, rank: Option<usize>, idle: bool) -> Duration {
+        ProbeSchedule::new(&self.config_repo).next_interval(target.consecutive_failures, rank, idle)
+    }
+}
+
+struct ProbeSchedule {
+    interval: Duration,
+    top_interval: Duration,
+    max_backoff: Duration,
+    top_ranked: usize,
+    lazy_interval: Duration,
+}
+
+impl ProbeSchedule {
+    fn new(config_repo: &ConfigRepo) -> Self {
+        Self {
+            interval: config_repo.probe_interval,
+            top_interval: config_repo.probe_top_interval,
+            max_backoff: config_repo.probe_max_backoff,
+            top_ranked: config_repo.probe_top_ranked,
+            lazy_interval: config_repo.lazy_probe_interval,
+        }
+    }
+
+    // failing rpcs back off exponentially, a success resets to the rank interval
+    fn next_interval(
+        &self,
+        consecutive_failures: u32,
+        rank: Option<usize>,
+        idle: bool,
+    ) -> Duration {
+        let interval = if consecutive_failures > 0 {
+            let exponent = (consecutive_failures - 1).min(MAX_BACKOFF_EXPONENT);
+            self.interval
+                .saturating_mul(2u32.pow(exponent))
+                .min(self.max_backoff)
+        } else {
+            match rank {
+                Some(rank) if rank < self.top_ranked => self.top_interval,
+                _ => self.interval,
+            }
+        };
+
+        if idle {
+            interval.max(self.lazy_interval)
+        } else {
+            interval
+        }
+    }
+}
+
+// median height of the sampled rpcs lowered by the check depth,
+// none when too few rpcs responded
+fn common_height(mut heights: Vec<u64>, depth: u64) -> Option<u64> {
+    if heights.len() < MIN_FORK_CHECK_RPCS {
+        return None;
+    }
+    heights.sort_unstable();
+    heights[heights.len() / 2].checked_sub(depth)
+}
+
+// branches by block hash, largest first, and rpcs outside of a strict majority.
+// none when every rpc has the same hash, nobody is quarantined without a majority
+fn split_branches(
+    hash_to_rpcs: HashMap<String, Vec<String>>,
+) -> Option<(Vec<ForkBranch>, Vec<String>)> {
+    if hash_to_rpcs.len() < 2 {
+        return None;
+    }
+
+    let sampled: usize = hash_to_rpcs.values().map(Vec::len).sum();
+    let mut branches: Vec<ForkBranch> = hash_to_rpcs
+        .into_iter()
+        .map(|(hash, rpcs)| ForkBranch { hash, rpcs })
+        .collect();
+    branches.sort_by_key(|branch| Reverse(branch.rpcs.len()));
+
+    let quarantined_rpcs: Vec<String> = if branches[0].rpcs.len() * 2 > sampled {
+        branches[1..]
+            .iter()
+            .flat_map(|branch| branch.rpcs.clone())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Some((branches, quarantined_rpcs))
+}
+
+// every transport is ranked separately, a ws rpc can't serve http requests and vice versa
+fn build_rankings(
+    targets: &HashMap<String, ProbeTarget>,
+) -> Vec<(Transport, Vec<(Upstream, RpcMetrics)>)> {
+    Transport::ALL
+        .iter()
+        .map(|transport| (*transport, build_ranking(targets, *transport)))
+        .collect()
+}
+
+fn build_ranking(
+    targets: &HashMap<String, ProbeTarget>,
+    transport: Transport,
+) -> Vec<(Upstream, RpcMetrics)> {
+    let now = Instant::now();
+    let targets = targets.iter().filter(|(_, target)| {
+        target.upstream.transport == transport && !target.is_quarantined(now)
+    });
+
+    let mut healthy: Vec<(Upstream, RpcMetrics)> = targets
+        .clone()
+        .filter_map(|(_, target)| match &target.state {
+            ProbeState::Healthy(metrics) => Some((target.upstream.clone(), metrics.clone())),
+            _ => None,
+        })
+        .collect();
+    healthy.sort_by(|(_, a), (_, b)| b.to_score().total_cmp(&a.to_score()));
+
+    let pending = targets
+        .filter(|(_, target)| matches!(target.state, ProbeState::Pending))
+        .map(|(_, target)| (target.upstream.clone(), RpcMetrics::unprobed()));
+
+    healthy.extend(pending);
+    healthy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_to_rpcs(branches: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        branches
+            .iter()
+            .map(|(hash, rpcs)| {
+                (
+                    hash.to_string(),
+                    rpcs.iter().map(|rpc| rpc.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn picks_median_height_below_depth() {
+        assert_eq!(common_height(vec![105, 100, 1_000, 102], 5), Some(100));
+        assert_eq!(common_height(vec![100, 90, 110], 0), Some(100));
+        assert_eq!(common_height(vec![3, 4, 5], 10), None);
+    }
+
+    #[test]
+    fn skips_checks_with_too_few_rpcs() {
+        assert_eq!(common_height(Vec::new(), 0), None);
+        assert_eq!(common_height(vec![100, 101], 0), None);
+    }
+
+    #[test]
+    fn quarantines_minority_fork() {
+        let (branches, quarantined_rpcs) = split_branches(hash_to_rpcs(&[
+            ("0xa", &["a1", "a2", "a3"]),
+            ("0xb", &["b1"]),
+        ]))
+        .unwrap();
+
+        assert_eq!(branches[0].hash, "0xa");
+        assert_eq!(branches[1].rpcs, vec!["b1"]);
+        assert_eq!(quarantined_rpcs, vec!["b1"]);
+    }
+
+    #[test]
+    fn quarantines_nobody_without_majority() {
+        let (branches, quarantined_rpcs) = split_branches(hash_to_rpcs(&[
+            ("0xa", &["a1", "a2"]),
+            ("0xb", &["b1", "b2"]),
+        ]))
+        .unwrap();
+        assert_eq!(branches.len(), 2);
+        assert!(quarantined_rpcs.is_empty());
+
+        // the largest branch has to be more than a half
+        let (_, quarantined_rpcs) = split_branches(hash_to_rpcs(&[
+            ("0xa", &["a1", "a2"]),
+            ("0xb", &["b1"]),
+            ("0xc", &["c1"]),
+        ]))
+        .unwrap();
+        assert!(quarantined_rpcs.is_empty());
+    }
+
+    #[test]
+    fn reports_no_fork_for_single_hash() {
+        assert!(split_branches(hash_to_rpcs(&[("0xa", &["a1", "a2", "a3"])])).is_none());
+        assert!(split_branches(HashMap::new()).is_none());
+    }
+
+    fn schedule() -> ProbeSchedule {
+        ProbeSchedule {
+            interval: Duration::from_secs(300),
+            top_interval: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(3600),
+            top_ranked: 3,
+            lazy_interval: Duration::from_secs(1800),
+        }
+    }
+
+    #[test]
+    fn backs_off_failing_rpcs() {
+        let schedule = schedule();
+
+        assert_eq!(
+            schedule.next_interval(1, Some(0), false),
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            schedule.next_interval(2, Some(0), false),
+            Duration::from_secs(600)
+        );
+        assert_eq!(
+            schedule.next_interval(4, None, false),
+            Duration::from_secs(2400)
+        );
+        assert_eq!(
+            schedule.next_interval(5, None, false),
+            Duration::from_secs(3600)
+        );
+        assert_eq!(
+            schedule.next_interval(u32::MAX, None, false),
+            Duration::from_secs(3600)
+        );
+    }
+
+    #[test]
+    fn resets_to_rank_interval() {
+        let schedule = schedule();
+
+        assert_eq!(
+            schedule.next_interval(0, Some(0), false),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            schedule.next_interval(0, Some(2), false),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            schedule.next_interval(0, Some(3), false),
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            schedule.next_interval(0, None, false),
+            Duration::from_secs(300)
+        );
+    }
+
+    #[test]
+    fn slows_idle_rpcs() {
+        let schedule = schedule();
+
+        assert_eq!(
+            schedule.next_interval(0, Some(0), true),
+            Duration::from_secs(1800)
+        );
+        assert_eq!(
+            schedule.next_interval(5, None, true),
+            Duration::from_secs(3600)
+        );
+    }
+}
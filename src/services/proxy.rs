@@ -1,80 +1,552 @@
-use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    future::Future,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
 
-use crate::{client::proxyseller::ProxysellerClient, models::proxy::ProxyConfig};
+use anyhow::{bail, Context, Result};
+use arc_swap::ArcSwap;
+use futures::future::join_all;
+use rocket::tokio::sync::{Mutex as AsyncMutex, RwLock};
 
-#[derive(PartialEq, Eq)]
-enum ProxyServiceState {
-    NotInitiated,
-    Initiated,
-    Other,
+use crate::{
+    client::{proxy_checker::ProxyChecker, proxy_provider::ProxyProvider},
+    models::{
+        bandwidth::{BandwidthCounter, ChainBandwidth, ProxyBandwidth, ProxySpend},
+        egress::{Egress, GeoPolicy},
+        proxy::{ProxyCheck, ProxyCheckMode, ProxyConfig, ProxyHealth, ProxySelection},
+        upstream::Upstream,
+    },
+    repo::{bandwidth::BandwidthRepo, config::ConfigRepo},
+    services::evm_rpc::EvmRpcError,
+    util::{unix_day, unix_timestamp},
+};
+
+// bandwidth of the proxy, the day rolls over on the first request of the next one
+struct ProxyUsage {
+    total: BandwidthCounter,
+    day: AtomicU64,
+    daily_bytes: AtomicU64,
 }
 
+impl ProxyUsage {
+    fn new(spend: ProxySpend) -> Self {
+        Self {
+            total: BandwidthCounter::default(),
+            day: AtomicU64::new(spend.day),
+            daily_bytes: AtomicU64::new(spend.daily_bytes),
+        }
+    }
+
+    fn record(&self, request_bytes: u64, response_bytes: u64, timestamp: u64) {
+        let day = unix_day(timestamp);
+        if self.day.swap(day, Ordering::Relaxed) != day {
+            self.daily_bytes.store(0, Ordering::Relaxed);
+        }
+        self.daily_bytes
+            .fetch_add(request_bytes + response_bytes, Ordering::Relaxed);
+        self.total.record(request_bytes, response_bytes);
+    }
+
+    // bytes of past days count as not spent
+    fn daily_bytes_at(&self, timestamp: u64) -> u64 {
+        if self.day.load(Ordering::Relaxed) == unix_day(timestamp) {
+            self.daily_bytes.load(Ordering::Relaxed)
+        } else {
+            0
+        }
+    }
+
+    fn to_spend(&self) -> ProxySpend {
+        ProxySpend {
+            day: self.day.load(Ordering::Relaxed),
+            daily_bytes: self.daily_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// health lock is held only to read or update the health, never across awaits
+struct PooledProxy {
+    config: ProxyConfig,
+    // without credentials, the key of the proxy in monitoring and snapshots
+    address: String,
+    daily_limit: Option<u64>,
+    health: Mutex<ProxyHealth>,
+    usage: ProxyUsage,
+}
+
+impl PooledProxy {
+    fn new(config: ProxyConfig, daily_limit: Option<u64>, spend: ProxySpend) -> Self {
+        Self {
+            address: config.address(),
+            daily_limit: config.daily_bytes.or(daily_limit),
+            health: Mutex::new(ProxyHealth::new(&config)),
+            usage: ProxyUsage::new(spend),
+            config,
+        }
+    }
+
+    fn daily_bytes_at(&self, timestamp: u64) -> u64 {
+        self.usage.daily_bytes_at(timestamp)
+    }
+
+    fn is_exhausted(&self, timestamp: u64) -> bool {
+        self.daily_limit
+            .is_some_and(|daily_limit| self.daily_bytes_at(timestamp) >= daily_limit)
+    }
+
+    fn health(&self) -> MutexGuard<'_, ProxyHealth> {
+        self.health.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// requests read a snapshot of the pool, reloads build a new pool aside and swap it in,
+// so the request path never waits on fetching or checking proxies.
+// without a provider rpcs are requested directly
 pub struct ProxyService {
-    proxy_client: Box<ProxysellerClient>,
-    proxy_id: usize,
-    proxies: Vec<ProxyConfig>,
-    state: ProxyServiceState,
+    proxy_provider: Option<Box<dyn ProxyProvider>>,
+    proxy_checker: ProxyChecker,
+    proxies: ArcSwap<Vec<Arc<PooledProxy>>>,
+    // serializes reloads and checks with each other
+    updating: AsyncMutex<()>,
+    cursor: AtomicUsize,
+    // proxy errors since the last reload
+    proxy_errors: AtomicU32,
+    // the last fetch failed entirely or for some parts, it is retried by the reloads
+    fetch_failed: AtomicBool,
+    // daily bytes of the snapshot, waiting for the pool to be fetched
+    restored_spends: Mutex<HashMap<String, ProxySpend>>,
+    bandwidth_repo: Arc<RwLock<BandwidthRepo>>,
+    config_repo: ConfigRepo,
 }
 
 impl ProxyService {
-    pub fn new(proxy_client: Box<ProxysellerClient>) -> Self {
+    pub fn new(
+        proxy_provider: Option<Box<dyn ProxyProvider>>,
+        proxy_checker: ProxyChecker,
+        bandwidth_repo: Arc<RwLock<BandwidthRepo>>,
+        config_repo: ConfigRepo,
+    ) -> Self {
         Self {
-            proxy_client,
-            proxy_id: 0,
-            proxies: Vec::new(),
-            state: ProxyServiceState::NotInitiated,
+            proxy_provider,
+            proxy_checker,
+            proxies: ArcSwap::from_pointee(Vec::new()),
+            updating: AsyncMutex::new(()),
+            cursor: AtomicUsize::new(0),
+            proxy_errors: AtomicU32::new(0),
+            fetch_failed: AtomicBool::new(false),
+            restored_spends: Mutex::new(HashMap::new()),
+            bandwidth_repo,
+            config_repo,
+        }
+    }
+
+    // picks a proxy for the request to rpc among the healthy ones of preferred countries,
+    // dropped proxies are used when no other is allowed so traffic never leaves directly
+    // unless every proxy is in a forbidden country. once every allowed proxy spent its
+    // daily bytes requests go direct, or through the least used proxy without direct fallback
+    fn next_proxy(
+        &self,
+        rpc: &str,
+        geo_policy: &GeoPolicy,
+        excluded: &[ProxyConfig],
+    ) -> Option<ProxyConfig> {
+        let proxies = self.proxies.load();
+        let timestamp = unix_timestamp();
+        let (exhausted, allowed): (Vec<&PooledProxy>, Vec<&PooledProxy>) = proxies
+            .iter()
+            .map(Arc::as_ref)
+            .filter(|proxy| {
+                geo_policy.allows(proxy.config.country.as_deref())
+                    && !excluded.contains(&proxy.config)
+            })
+            .partition(|proxy| proxy.is_exhausted(timestamp));
+        if allowed.is_empty() {
+            if self.config_repo.proxy_direct_fallback {
+                return None;
+            }
+            return exhausted
+                .into_iter()
+                .min_by_key(|proxy| proxy.daily_bytes_at(timestamp))
+                .map(|proxy| proxy.config.clone());
+        }
+
+        let healthy: Vec<&PooledProxy> = allowed
+            .iter()
+            .filter(|proxy| !proxy.health().dropped)
+            .copied()
+            .collect();
+        let preferred: Vec<&PooledProxy> = healthy
+            .iter()
+            .filter(|proxy| geo_policy.prefers(proxy.config.country.as_deref()))
+            .copied()
+            .collect();
+        let pool = [preferred, healthy, allowed]
+            .into_iter()
+            .find(|pool| !pool.is_empty())?;
+
+        let index = match self.config_repo.proxy_selection {
+            ProxySelection::RoundRobin => self.cursor.fetch_add(1, Ordering::Relaxed),
+            ProxySelection::Pinned => {
+                let mut hasher = DefaultHasher::new();
+                rpc.hash(&mut hasher);
+                hasher.finish() as usize
+            }
+        };
+
+        Some(pool[index % pool.len()].config.clone())
+    }
+
+    // countries of the upstream take precedence over the ones of the chain
+    fn geo_policy_for<'a>(&'a self, chain_id: &str, upstream: &'a Upstream) -> &'a GeoPolicy {
+        upstream
+            .geo
+            .as_ref()
+            .unwrap_or(self.config_repo.geo_policy_for(chain_id))
+    }
+
+    pub fn proxy_for(
+        &self,
+        chain_id: &str,
+        upstream: &Upstream,
+        egress: Egress,
+    ) -> Option<ProxyConfig> {
+        match egress {
+            Egress::Direct => None,
+            Egress::Proxied => {
+                self.next_proxy(&upstream.url, self.geo_policy_for(chain_id, upstream), &[])
+            }
+        }
+    }
+
+    // sends the request through the given egress, failures on the proxy layer are charged
+    // to the proxy and the request is repeated through another proxy or directly.
+    // returns the response together with the proxy which carried it
+    pub async fn request<T, F, Fut>(
+        &self,
+        chain_id: &str,
+        upstream: &Upstream,
+        egress: Egress,
+        request: F,
+    ) -> (Result<T, EvmRpcError>, Option<ProxyConfig>)
+    where
+        F: Fn(Option<ProxyConfig>) -> Fut,
+        Fut: Future<Output = Result<T, EvmRpcError>>,
+    {
+        let mut proxy_config = self.proxy_for(chain_id, upstream, egress);
+        let mut failed_proxies: Vec<ProxyConfig> = Vec::new();
+        loop {
+            let start = Instant::now();
+            let response = request(proxy_config.clone()).await;
+            let Some(proxy) = proxy_config else {
+                return (response, None);
+            };
+
+            let proxy_failed = matches!(response, Err(EvmRpcError::Proxy(_)));
+            self.record_result(&proxy, start.elapsed(), !proxy_failed);
+            if !proxy_failed {
+                return (response, Some(proxy));
+            }
+
+            log::debug!(
+                "request to {} failed on proxy {}: {}",
+                upstream.url,
+                proxy.address(),
+                response
+                    .as_ref()
+                    .err()
+                    .map(ToString::to_string)
+                    .unwrap_or_default()
+            );
+            failed_proxies.push(proxy);
+            let next_proxy = if failed_proxies.len() <= self.config_repo.proxy_failover_attempts {
+                self.next_proxy(
+                    &upstream.url,
+                    self.geo_policy_for(chain_id, upstream),
+                    &failed_proxies,
+                )
+            } else {
+                None
+            };
+            proxy_config = match next_proxy {
+                Some(next_proxy) => Some(next_proxy),
+                None if self.config_repo.proxy_direct_fallback => None,
+                None => return (response, failed_proxies.pop()),
+            };
+        }
+    }
+
+    // failed means the request did not get through the proxy, rpc errors count as success
+    pub fn record_result(&self, proxy_config: &ProxyConfig, latency: Duration, success: bool) {
+        let proxies = self.proxies.load();
+        let Some(proxy) = proxies.iter().find(|proxy| &proxy.config == proxy_config) else {
+            return;
+        };
+
+        if !success {
+            self.proxy_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut health = proxy.health();
+        health.record(
+            latency.as_millis() as f32,
+            success,
+            self.config_repo.traffic_ewma_alpha,
+        );
+        if !health.dropped && health.consecutive_failures >= self.config_repo.proxy_max_failures {
+            log::warn!(
+                "dropped proxy {} after {} failed requests",
+                health.proxy,
+                health.consecutive_failures
+            );
+            health.dropped = true;
         }
     }
 
-    pub fn get_proxy(&self) -> Option<&ProxyConfig> {
-        self.proxies.get(self.proxy_id)
+    pub fn get_proxy_healths(&self) -> Vec<ProxyHealth> {
+        self.proxies
+            .load()
+            .iter()
+            .map(|proxy| proxy.health().clone())
+            .collect()
     }
 
-    pub async fn rotate_proxy(&mut self) -> Result<()> {
-        let length = self.proxies.len();
-        if length == 0 {
+    // proxied requests which reached the upstream, counters of the upstream are created
+    // on its first request and updated under a read lock afterwards
+    pub async fn record_bandwidth(
+        &self,
+        chain_id: &str,
+        rpc: &str,
+        proxy_config: &ProxyConfig,
+        request_bytes: u64,
+        response_bytes: u64,
+    ) {
+        if let Some(proxy) = self
+            .proxies
+            .load()
+            .iter()
+            .find(|proxy| &proxy.config == proxy_config)
+        {
+            proxy
+                .usage
+                .record(request_bytes, response_bytes, unix_timestamp());
+        }
+
+        let counter = self.bandwidth_repo.read().await.get_counter(chain_id, rpc);
+        let counter = match counter {
+            Some(counter) => counter,
+            None => self.bandwidth_repo.write().await.add_counter(chain_id, rpc),
+        };
+        counter.record(request_bytes, response_bytes);
+    }
+
+    pub fn get_proxy_bandwidths(&self) -> Vec<ProxyBandwidth> {
+        let timestamp = unix_timestamp();
+        self.proxies
+            .load()
+            .iter()
+            .map(|proxy| ProxyBandwidth {
+                proxy: proxy.address.clone(),
+                total: proxy.usage.total.load(),
+                daily_bytes: proxy.daily_bytes_at(timestamp),
+                daily_limit: proxy.daily_limit,
+                exhausted: proxy.is_exhausted(timestamp),
+            })
+            .collect()
+    }
+
+    pub fn get_proxy_spends(&self) -> HashMap<String, ProxySpend> {
+        self.proxies
+            .load()
+            .iter()
+            .map(|proxy| (proxy.address.clone(), proxy.usage.to_spend()))
+            .collect()
+    }
+
+    // applied to proxies when the pool is built, so restore before the first init
+    pub fn restore_proxy_spends(&self, address_to_spend: HashMap<String, ProxySpend>) {
+        *self
+            .restored_spends
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = address_to_spend;
+    }
+
+    pub async fn get_chain_bandwidths(&self) -> Vec<ChainBandwidth> {
+        self.bandwidth_repo.read().await.get_chain_bandwidths()
+    }
+
+    // checks the whole pool, dropped proxies which pass the check get traffic again.
+    // checks run on a snapshot, results are applied to proxies which may be already
+    // replaced by a reload, those are not served anymore
+    pub async fn check_proxies(&self) -> Result<()> {
+        let Some(proxy_provider) = &self.proxy_provider else {
+            return Ok(());
+        };
+        let _updating = self.updating.lock().await;
+        let proxies = self.proxies.load_full();
+        if proxies.is_empty() {
             bail!("proxies length is zero");
         }
 
-        let inital_proxy_id = self.proxy_id;
+        let checks = join_all(
+            proxies
+                .iter()
+                .map(|proxy| self.check_proxy(proxy_provider.as_ref(), &proxy.config)),
+        )
+        .await;
 
-        loop {
-            match self.state {
-                ProxyServiceState::NotInitiated => bail!("firstly call init_proxies() function"),
-                ProxyServiceState::Initiated => self.state = ProxyServiceState::Other,
-                ProxyServiceState::Other => {
-                    self.proxy_id = (self.proxy_id + 1) % length;
-                    if inital_proxy_id == self.proxy_id {
-                        bail!("failed to find good proxy")
-                    }
+        let mut healthy = 0;
+        for (proxy, check) in proxies.iter().zip(checks) {
+            let mut health = proxy.health();
+            let passed = match check {
+                Ok(check) => {
+                    health.last_check = check.or(health.last_check.take());
+                    true
                 }
+                Err(err) => {
+                    log::warn!("failed to check proxy {}: {err:#}", health.proxy);
+                    false
+                }
+            };
+            if passed && health.dropped {
+                log::info!("restored proxy {}", health.proxy);
+                health.consecutive_failures = 0;
+            }
+            health.dropped = !passed;
+            if passed {
+                healthy += 1;
+            }
+        }
+
+        if healthy == 0 {
+            bail!("failed to find good proxy");
+        }
+        log::info!("{healthy} of {} proxies are healthy", proxies.len());
+
+        Ok(())
+    }
+
+    // provider checks have no timings
+    async fn check_proxy(
+        &self,
+        proxy_provider: &dyn ProxyProvider,
+        proxy_config: &ProxyConfig,
+    ) -> Result<Option<ProxyCheck>> {
+        match self.config_repo.proxy_check {
+            ProxyCheckMode::Builtin => self.proxy_checker.check(proxy_config).await.map(Some),
+            ProxyCheckMode::Provider => {
+                if !proxy_provider.check_proxy(proxy_config).await? {
+                    bail!("rejected by {}", proxy_provider.name());
+                }
+                Ok(None)
             }
+        }
+    }
 
-            let proxy_config = self.get_proxy().ok_or(anyhow!("failed to get proxy"))?;
-            let response = self
-                .proxy_client
-                .check_proxy(&proxy_config)
-                .await
-                .context("failed to check proxy")?;
+    // proxies which stay in the pool keep their health
+    pub async fn init_proxies(&self) -> Result<()> {
+        let Some(proxy_provider) = &self.proxy_provider else {
+            return Ok(());
+        };
+        let _updating = self.updating.lock().await;
 
-            if response {
-                break;
+        let fetched = proxy_provider.fetch_proxies().await.context(format!(
+            "failed to fetch proxies from {}",
+            proxy_provider.name()
+        ));
+        let fetched = match fetched {
+            Ok(fetched) => fetched,
+            Err(err) => {
+                self.fetch_failed.store(true, Ordering::Relaxed);
+                return Err(err);
             }
+        };
+        self.fetch_failed
+            .store(!fetched.failed_tags.is_empty(), Ordering::Relaxed);
+
+        let current = self.proxies.load_full();
+        let restored_spends = std::mem::take(
+            &mut *self
+                .restored_spends
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        let mut proxies: Vec<Arc<PooledProxy>> = fetched
+            .proxies
+            .into_iter()
+            .map(
+                |config| match current.iter().find(|proxy| proxy.config == config) {
+                    Some(proxy) => proxy.clone(),
+                    None => {
+                        let spend = restored_spends
+                            .get(&config.address())
+                            .copied()
+                            .unwrap_or_default();
+                        Arc::new(PooledProxy::new(
+                            config,
+                            self.config_repo.proxy_daily_bytes,
+                            spend,
+                        ))
+                    }
+                },
+            )
+            .collect();
+
+        // proxies of parts which failed to be fetched are not evicted
+        let kept: Vec<Arc<PooledProxy>> = current
+            .iter()
+            .filter(|proxy| {
+                proxy
+                    .config
+                    .tag
+                    .as_ref()
+                    .is_some_and(|tag| fetched.failed_tags.contains(tag))
+                    && !proxies.iter().any(|pooled| pooled.config == proxy.config)
+            })
+            .cloned()
+            .collect();
+        if !kept.is_empty() {
+            log::warn!(
+                "keeping {} proxies of failed {} parts: {}",
+                kept.len(),
+                proxy_provider.name(),
+                fetched.failed_tags.join(", ")
+            );
+            proxies.extend(kept);
         }
+        self.proxies.store(Arc::new(proxies));
 
         Ok(())
     }
 
-    pub async fn init_proxies(&mut self) -> Result<()> {
-        self.proxies = self
-            .proxy_client
-            .fetch_proxies()
-            .await
-            .context("failed to fetch proxies")?;
+    // the pool is rotated, fetched and checked again, when proxies fail too often.
+    // failed fetches and an empty pool are fetched again on every reload
+    pub async fn reload_proxies(&self) -> Result<()> {
+        let Some(proxy_provider) = &self.proxy_provider else {
+            return Ok(());
+        };
 
-        self.proxy_id = 0;
-        self.state = ProxyServiceState::Initiated;
+        let proxy_errors = self.proxy_errors.swap(0, Ordering::Relaxed);
+        if proxy_errors >= self.config_repo.proxy_rotate_errors {
+            log::warn!("rotating proxies after {proxy_errors} proxy errors");
+            self.init_proxies().await?;
+            return self.check_proxies().await;
+        }
 
-        Ok(())
+        if self.fetch_failed.load(Ordering::Relaxed) || self.proxies.load().is_empty() {
+            log::info!("fetching proxies from {} again", proxy_provider.name());
+            return self.init_proxies().await;
+        }
+
+        if !proxy_provider.has_changed().await {
+            return Ok(());
+        }
+
+        log::info!("reloading changed proxies from {}", proxy_provider.name());
+        self.init_proxies().await
     }
 }
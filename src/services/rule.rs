@@ -0,0 +1,60 @@
+use rocket::tokio::sync::Mutex;
+
+use crate::{
+    models::{
+        rule::{check_upstream, BannedUpstream, UpstreamRule},
+        upstream::Upstream,
+    },
+    repo::rule::RuleRepo,
+    util::unix_timestamp,
+};
+
+pub struct RuleService {
+    rule_repo: RuleRepo,
+    rules: Mutex<Vec<UpstreamRule>>,
+}
+
+impl RuleService {
+    pub fn new(rule_repo: RuleRepo) -> Self {
+        Self {
+            rule_repo,
+            rules: Mutex::new(Vec::new()),
+        }
+    }
+
+    // previous rules stay in force when the rules file can't be read
+    pub async fn reload(&self) {
+        match self.rule_repo.load().await {
+            Ok(rules) => *self.rules.lock().await = rules,
+            Err(err) => log::error!("failed to load upstream rules: {err:#}"),
+        }
+    }
+
+    pub async fn filter(
+        &self,
+        chain_id: &str,
+        upstreams: &[Upstream],
+    ) -> (Vec<Upstream>, Vec<BannedUpstream>) {
+        let timestamp = unix_timestamp();
+        let rules = self.rules.lock().await;
+        // own endpoints listed in chainlist would route traffic back to us
+        let builtin_rules = [UpstreamRule::block_url(
+            "chainlist",
+            "*polysplit*",
+            "polysplit endpoint",
+        )];
+
+        let mut allowed: Vec<Upstream> = Vec::new();
+        let mut banned: Vec<BannedUpstream> = Vec::new();
+        for upstream in upstreams {
+            let ban = check_upstream(&builtin_rules, chain_id, upstream, timestamp)
+                .or_else(|| check_upstream(&rules, chain_id, upstream, timestamp));
+            match ban {
+                Some(ban) => banned.push(ban),
+                None => allowed.push(upstream.clone()),
+            }
+        }
+
+        (allowed, banned)
+    }
+}
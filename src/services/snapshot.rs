@@ -0,0 +1,121 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use rocket::tokio::sync::Mutex;
+
+use crate::{
+    client::source::ChainToUpstreams,
+    models::{
+        snapshot::{Snapshot, SNAPSHOT_VERSION},
+        upstream::{Transport, Upstream},
+    },
+    repo::{config::ConfigRepo, snapshot::SnapshotRepo},
+    services::{
+        evm_rpc::{EvmRpcService, RpcMetrics},
+        proxy::ProxyService,
+    },
+    util::unix_timestamp,
+};
+
+pub struct SnapshotService {
+    snapshot_repo: SnapshotRepo,
+    evm_rpc_service: Arc<EvmRpcService>,
+    proxy_service: Arc<ProxyService>,
+    config_repo: ConfigRepo,
+    snapshot: Mutex<Snapshot>,
+}
+
+impl SnapshotService {
+    pub fn new(
+        snapshot_repo: SnapshotRepo,
+        evm_rpc_service: Arc<EvmRpcService>,
+        proxy_service: Arc<ProxyService>,
+        config_repo: ConfigRepo,
+    ) -> Self {
+        Self {
+            snapshot_repo,
+            evm_rpc_service,
+            proxy_service,
+            config_repo,
+            snapshot: Mutex::new(Snapshot::default()),
+        }
+    }
+
+    pub async fn load(&self) -> Result<()> {
+        let Some(snapshot) = self.snapshot_repo.load().await? else {
+            return Ok(());
+        };
+
+        log::info!("loaded snapshot saved at {}", snapshot.saved_at);
+        self.evm_rpc_service
+            .restore_budget_spends(snapshot.budget_spends.clone())
+            .await;
+        self.proxy_service
+            .restore_proxy_spends(snapshot.proxy_spends.clone());
+        *self.snapshot.lock().await = snapshot;
+        Ok(())
+    }
+
+    pub async fn get_source_upstreams(&self) -> HashMap<String, ChainToUpstreams> {
+        self.snapshot.lock().await.source_upstreams.clone()
+    }
+
+    pub async fn get_rpcs_for_chain_id(&self, chain_id: &str) -> Vec<(Upstream, RpcMetrics)> {
+        self.snapshot
+            .lock()
+            .await
+            .chain_id_to_rpcs
+            .get(chain_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn get_discovered_chain_ids(&self) -> Vec<String> {
+        self.snapshot.lock().await.discovered_chain_ids.clone()
+    }
+
+    // stores the given source upstreams together with the current rankings of supported
+    // and discovered chains, chains without ranked rpcs keep the previously saved ones
+    pub async fn save(
+        &self,
+        source_upstreams: HashMap<String, ChainToUpstreams>,
+        discovered_chain_ids: Vec<String>,
+    ) -> Result<()> {
+        let mut snapshot = self.snapshot.lock().await;
+        let mut chain_id_to_rpcs: HashMap<String, Vec<(Upstream, RpcMetrics)>> = HashMap::new();
+        for chain_id in self
+            .config_repo
+            .supported_chain_ids
+            .iter()
+            .chain(&discovered_chain_ids)
+        {
+            let mut rpcs: Vec<(Upstream, RpcMetrics)> = Vec::new();
+            for transport in Transport::ALL {
+                rpcs.extend(
+                    self.evm_rpc_service
+                        .get_rpcs_for_chain_id(chain_id, transport)
+                        .await
+                        .unwrap_or_default(),
+                );
+            }
+            if rpcs.is_empty() {
+                rpcs = snapshot
+                    .chain_id_to_rpcs
+                    .remove(chain_id)
+                    .unwrap_or_default();
+            }
+            chain_id_to_rpcs.insert(chain_id.clone(), rpcs);
+        }
+
+        *snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            saved_at: unix_timestamp(),
+            source_upstreams,
+            chain_id_to_rpcs,
+            discovered_chain_ids,
+            budget_spends: self.evm_rpc_service.get_budget_spends().await,
+            proxy_spends: self.proxy_service.get_proxy_spends(),
+        };
+        self.snapshot_repo.save(&snapshot).await
+    }
+}
@@ -1,22 +1,29 @@
 use std::sync::Arc;
 
 use rocket::catchers;
-use rocket::{http::Method, routes, tokio::sync::RwLock, Build, Rocket};
+use rocket::{http::Method, routes, Build, Rocket};
 use rocket_cors::{AllowedOrigins, CorsOptions};
 use rocket_governor::rocket_governor_catcher;
 use rocket_okapi::{openapi_get_routes, rapidoc::*, settings::UrlObject, swagger_ui::*};
 
 use crate::controllers::status;
 use crate::controllers::v1::chain;
+use crate::controllers::v1::chains;
 use crate::controllers::v1::monitoring;
 use crate::repo::config::ConfigRepo;
+use crate::services::chain::ChainService;
 use crate::services::evm_rpc::EvmRpcService;
+use crate::services::history::HistoryService;
 use crate::services::monitoring::MonitoringService;
+use crate::services::probe::ProbeService;
 use crate::services::proxy::ProxyService;
 
 pub fn setup_app(
     evm_rpc_service: Arc<EvmRpcService>,
-    proxy_service: Arc<RwLock<ProxyService>>,
+    chain_service: Arc<ChainService>,
+    probe_service: Arc<ProbeService>,
+    history_service: Arc<HistoryService>,
+    proxy_service: Arc<ProxyService>,
     monitoring_service: Arc<MonitoringService>,
     config_repo: ConfigRepo,
 ) -> Rocket<Build> {
@@ -34,6 +41,9 @@ pub fn setup_app(
 
     rocket::build()
         .manage(evm_rpc_service)
+        .manage(chain_service)
+        .manage(probe_service)
+        .manage(history_service)
         .manage(proxy_service)
         .manage(config_repo)
         .manage(monitoring_service)
@@ -44,6 +54,9 @@ pub fn setup_app(
             openapi_get_routes![
                 status::get_health,
                 chain::get_metrics_v1,
+                chain::get_rpc_history_v1,
+                chains::get_chains_v1,
+                chains::get_chain_v1,
                 monitoring::get_monitoring_v1
             ],
         )
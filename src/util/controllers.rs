@@ -87,4 +87,3 @@ impl<T> ResponseData<T> {
 
 pub type ResponseResult<T> = Result<Json<T>, ResponseError>;
 pub type ResponseResultData<T> = ResponseResult<ResponseData<T>>;
-pub type RequestResult<'a, T> = Result<Json<T>, rocket::serde::json::Error<'a>>;
@@ -7,8 +7,63 @@
 // use serde::{Deserialize, Serialize};
 // use uuid::Uuid;
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 pub mod controllers;
 
+const SECONDS_PER_DAY: u64 = 86_400;
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// FNV-1a hash, stays the same between restarts and builds unlike `DefaultHasher`
+pub fn stable_id(value: &str) -> String {
+    let hash = value.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    });
+    format!("{hash:016x}")
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+// exponentially weighted moving average, the first sample is taken as is
+pub fn ewma(average: f32, sample: f32, alpha: f32, seeded: bool) -> f32 {
+    if seeded {
+        average + alpha * (sample - average)
+    } else {
+        sample
+    }
+}
+
+// days since unix epoch, in UTC
+pub fn unix_day(timestamp: u64) -> u64 {
+    timestamp / SECONDS_PER_DAY
+}
+
+// months since year 0 (`year * 12 + month - 1`), in UTC
+pub fn unix_month(timestamp: u64) -> u64 {
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = unix_day(timestamp) + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    year * 12 + month - 1
+}
+
 // pub type Hash = String;
 // pub fn password_hash(s: String) -> Option<Hash> {
 //     bcrypt::hash(&s).ok()
@@ -77,3 +132,31 @@ pub mod controllers;
 //
 //     Ok(user_jwt.claims.user)
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_average_towards_samples() {
+        assert_eq!(ewma(0.0, 80.0, 0.5, false), 80.0);
+        assert_eq!(ewma(80.0, 40.0, 0.5, true), 60.0);
+        assert_eq!(ewma(60.0, 60.0, 0.2, true), 60.0);
+        assert_eq!(ewma(1.0, 0.0, 1.0, true), 0.0);
+    }
+
+    #[test]
+    fn counts_months_since_year_zero() {
+        // 1970-01-01
+        assert_eq!(unix_month(0), 1970 * 12);
+        // 2024-02-29 23:59:59, leap day
+        assert_eq!(unix_month(1_709_251_199), 2024 * 12 + 1);
+        // 2024-03-01 00:00:00
+        assert_eq!(unix_month(1_709_251_200), 2024 * 12 + 2);
+        // 2023-12-31 23:59:59 and 2024-01-01 00:00:00
+        assert_eq!(unix_month(1_704_067_199), 2023 * 12 + 11);
+        assert_eq!(unix_month(1_704_067_200), 2024 * 12);
+        // 2000-02-29, leap day of a century
+        assert_eq!(unix_month(951_782_400), 2000 * 12 + 1);
+    }
+}
@@ -0,0 +1,75 @@
+import { mergeDeep } from "../utils/fetch.js";
+import { llamaNodesRpcs } from "./llamaNodesRpcs.js";
+
+const privacyStatement = {
+  llamarpc: "No user data is collected by LlamaNodes",
+  ankr:
+    "For service delivery purposes, we temporarily record IP addresses to set usage limits and monitor for denial of service attacks against our infrastructure.\nhttps://www.ankr.com/blog/ankrs-ip-address-policy-and-your-privacy/",
+  omnia: `All the data and metadata remain private to the users. No third party is able to access, analyze or track it.`,
+  drpc: "Tracks IP address, location and usage data. https://drpc.org/privacy-policy",
+};
+
+/* entries shared between chains */
+const drpcEthereum = {
+  url: "https://eth.drpc.org",
+  tracking: "none",
+  trackingDetails: privacyStatement.drpc,
+};
+
+const bscDataseeds = [
+  "https://bsc-dataseed.bnbchain.org",
+  "https://bsc-dataseed1.defibit.io",
+];
+
+export const extraRpcs = {
+  1: {
+    rpcs: [
+      {
+        url: "https://eth.llamarpc.com",
+        tracking: "none",
+        trackingDetails: privacyStatement.llamarpc,
+      },
+      // plain url without tracking metadata
+      "https://endpoints.omniatech.io/v1/eth/mainnet/public",
+      {
+        url: "https://rpc.ankr.com/eth",
+        tracking: "limited",
+        trackingDetails: privacyStatement.ankr,
+      },
+      {
+        url: "wss://ethereum-rpc.publicnode.com",
+        tracking: "none",
+        trackingDetails: privacyStatement.publicnode,
+      },
+      drpcEthereum,
+    ],
+  },
+  56: {
+    rpcs: [
+      ...bscDataseeds,
+      {
+        url: 'https://binance.llamarpc.com',
+        tracking: "none",
+        trackingDetails: privacyStatement["llamarpc"],
+      },
+    ],
+  },
+  137: {
+    rpcs: ["https://polygon-rpc.com", { ...drpcEthereum, url: "https://polygon.drpc.org" }],
+  },
+  10: {
+    rpcs: [
+      ...llamaNodesRpcs[10].rpcs,
+      "https://mainnet.optimism.io",
+      `https://optimism-mainnet.infura.io/v3/${INFURA_API_KEY}`,
+    ],
+  },
+  42161: {
+    rpcs: [],
+  },
+  "0x1": { rpcs: ["https://not-a-chain-id.io"] },
+};
+
+const allExtraRpcs = mergeDeep(llamaNodesRpcs, extraRpcs);
+
+export default allExtraRpcs;
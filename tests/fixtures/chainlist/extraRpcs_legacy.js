@@ -0,0 +1,19 @@
+import { llamaNodesRpcs } from "./llamaNodesRpcs";
+import { mergeDeep } from "../utils/fetch";
+
+export const extraRpcs = {
+  1: {
+    rpcs: [
+      "https://api.mycryptoapi.com/eth",
+      "https://cloudflare-eth.com",
+      "https://rpc.polysplit.cloud/v1/chain/1",
+    ],
+  },
+  56: {
+    rpcs: ["https://bsc-dataseed.binance.org"],
+  },
+};
+
+const allExtraRpcs = mergeDeep(llamaNodesRpcs, extraRpcs);
+
+export default allExtraRpcs;